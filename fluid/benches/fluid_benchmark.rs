@@ -1,4 +1,19 @@
-// use criterion::{black_box, criterion_group, criterion_main, Criterion};
-// use fluid::{Config, Fluid};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use fluid::{Config, Fluid};
 
-// fn benchmark_update_fluid(c: &mut Criterion) {}
+fn benchmark_update_fluid(c: &mut Criterion) {
+    let config: Config = Config::new();
+    let mut fluid: Fluid = Fluid::construct(&config);
+    fluid.assert_boundary_conditions();
+
+    c.bench_function("update_fluid", |b| {
+        b.iter(|| fluid.update_fluid(black_box(true), black_box(true), black_box(true), black_box(true)))
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = benchmark_update_fluid
+}
+criterion_main!(benches);
@@ -0,0 +1,512 @@
+//! airfoil sections
+//!
+//! profiles are kept at unit chord with the leading edge at the origin, the trailing edge at (1, 0)
+//! and y pointing up, the way they are published. <Airfoil::placed> turns one into a grid polygon at
+//! a given chord, position and angle of attack
+//!
+//! sections can be generated from a naca designation or read from the selig and lednicer <.dat>
+//! coordinate files airfoil databases distribute. selig files list one loop from the trailing edge
+//! over the upper surface and back along the lower one. lednicer files give the point counts on the
+//! second line, then the upper and lower surfaces separately, both starting at the leading edge
+
+use crate::error;
+use crate::geometry;
+use crate::utils;
+
+use std::{f32::consts::PI, fs};
+
+use error::FluidError;
+use geometry::Polygon;
+use utils::Vector;
+
+/// points per surface for generated sections. dense enough that the outline is smooth at any chord
+/// that fits on a sensible grid
+pub const NACA_SAMPLES: usize = 80;
+
+/// unit-chord airfoil outline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Airfoil {
+    pub name: String,
+    pub points: Vec<Vector<f32>>,
+}
+
+impl Airfoil {
+    pub fn from_dat(path: &str) -> Result<Airfoil, FluidError> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|err| FluidError::Io { path: path.to_string(), reason: err.to_string() })?;
+        Airfoil::parse_dat(path, &text)
+    }
+
+    /// parses selig or lednicer coordinates and normalises them to unit chord. the chord runs from the
+    /// trailing edge (midway between the two ends of the loop) to the point furthest from it, so
+    /// sections stored pitched or offset come out level. <path> is only used to label errors
+    ///
+    /// a first pair of whole numbers is taken as the lednicer point counts when the rest of the file
+    /// holds exactly that many points or when a blank line sets it apart, otherwise it's the first
+    /// point of a selig loop that just isn't stored at unit chord
+    pub fn parse_dat(path: &str, text: &str) -> Result<Airfoil, FluidError> {
+        let error = |line: usize, reason: String| FluidError::Parse { path: path.to_string(), line, reason };
+
+        let mut lines = text.lines().enumerate().skip_while(|(_, line)| line.trim().is_empty());
+        let name: String = lines.next().map(|(_, line)| line.trim().to_string()).unwrap_or_default();
+
+        // blank lines separate the lednicer surfaces, so keep track of them while reading pairs
+        let mut surfaces: Vec<Vec<Vector<f32>>> = vec![Vec::new()];
+        let mut last_line: usize = 0;
+        for (idx, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if last_line + 1 != idx && !surfaces[surfaces.len() - 1].is_empty() {
+                surfaces.push(Vec::new());
+            }
+            last_line = idx;
+
+            let values: Vec<f32> = line
+                .split_whitespace()
+                .map(|token| token.parse().map_err(|_| error(idx + 1, format!("expected a number, got '{}'", token))))
+                .collect::<Result<_, _>>()?;
+            let [x, y] = values[..]
+            else {
+                return Err(error(idx + 1, format!("expected an x y pair, got {} values", values.len())));
+            };
+
+            surfaces.last_mut().unwrap().push(Vector::construct(x, y));
+        }
+
+        let all: Vec<Vector<f32>> = surfaces.concat();
+        let counts = match all.first() {
+            Some(first) if first.x > 1.5 && first.y > 1.5 && first.x.fract() == 0.0 && first.y.fract() == 0.0 => {
+                Some((first.x as usize, first.y as usize))
+                    .filter(|(upper, lower)| upper + lower == all.len() - 1 || surfaces[0].len() == 1)
+            }
+            _ => None,
+        };
+        let mut points: Vec<Vector<f32>> = match counts {
+            None => all,
+            Some((upper, lower)) => {
+                let all = &all[1..];
+                if all.len() != upper + lower {
+                    let reason = format!("header lists {} points but {} were read", upper + lower, all.len());
+                    return Err(error(last_line + 1, reason));
+                }
+
+                // walk the upper surface backwards so the loop runs trailing edge, leading edge, trailing edge
+                let (top, bottom) = all.split_at(upper);
+                let mut points: Vec<Vector<f32>> = top.iter().rev().copied().collect();
+                let shared = usize::from(bottom.first() == top.first());
+                points.extend(bottom.iter().skip(shared));
+                points
+            }
+        };
+        if points.len() < 3 {
+            return Err(error(last_line + 1, String::from("an airfoil needs at least 3 points")));
+        }
+
+        // found before dropping a repeated closing point, so a closed trailing edge stays on that point
+        let (first, last) = (points[0], points[points.len() - 1]);
+        let trailing = Vector::construct((first.x + last.x) * 0.5, (first.y + last.y) * 0.5);
+        if first == last {
+            points.pop();
+        }
+
+        let distance = |point: Vector<f32>| Vector::construct(point.x - trailing.x, point.y - trailing.y).magnitude();
+        let leading = points
+            .iter()
+            .fold(trailing, |far, point| if distance(*point) > distance(far) { *point } else { far });
+
+        let chord = distance(leading);
+        if !chord.is_finite() || chord == 0.0 {
+            return Err(error(last_line + 1, String::from("airfoil has no chord length")));
+        }
+        let pitch = (trailing.y - leading.y).atan2(trailing.x - leading.x).to_degrees();
+        let normalised = Polygon::construct(points)
+            .translated(-leading.x, -leading.y)
+            .rotated(-pitch, Vector::construct(0.0, 0.0))
+            .scaled(1.0 / chord, 1.0 / chord);
+
+        Ok(Airfoil { name, points: normalised.points })
+    }
+
+    /// grid polygon with the leading edge at (x, y) before pitching. the section is pitched nose up by
+    /// <aoa> degrees about its quarter chord, flow being left to right
+    pub fn placed(&self, x: f32, y: f32, chord: f32, aoa: f32) -> Polygon {
+        Polygon::construct(self.points.clone())
+            .scaled(chord, -chord)
+            .translated(x, y)
+            .rotated(aoa, Vector::construct(x + chord * 0.25, y))
+    }
+}
+
+/// naca 4- and 5-digit sections
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Naca {
+    /// MPTT: max camber <camber> at <position>, both as fractions of chord, and <thickness>
+    Four { camber: f32, position: f32, thickness: f32 },
+
+    /// LPQTT: design lift coefficient <design_lift>, max camber position digit <position> (1-5, in
+    /// twentieths of chord) and whether the camber line is reflexed
+    Five { design_lift: f32, position: u8, reflex: bool, thickness: f32 },
+}
+
+/// standard 5-digit mean lines, indexed by the position digit: (r, k1) for a design lift of 0.3
+const FIVE_STANDARD: [(f32, f32); 5] =
+    [(0.0580, 361.400), (0.1260, 51.640), (0.2025, 15.957), (0.2900, 6.643), (0.3910, 3.230)];
+
+/// reflexed 5-digit mean lines, position digits 2-5: (r, k1, k2 / k1). 0.3180 is the tabulated r,
+/// not an approximation of 1/pi
+#[allow(clippy::approx_constant)]
+const FIVE_REFLEX: [(f32, f32, f32); 4] =
+    [(0.1300, 51.990, 0.000764), (0.2170, 15.793, 0.00677), (0.3180, 6.520, 0.0303), (0.4410, 3.191, 0.1355)];
+
+impl Naca {
+    /// reads a designation like <2412> or <23012>, with or without a leading "naca"
+    pub fn parse(designation: &str) -> Result<Naca, FluidError> {
+        let invalid = |reason: String| FluidError::InvalidConfig { key: String::from("naca"), reason };
+
+        let trimmed = designation.trim();
+        let digits = trimmed
+            .strip_prefix("naca")
+            .or(trimmed.strip_prefix("NACA"))
+            .unwrap_or(trimmed)
+            .trim_start_matches([' ', '-']);
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid(format!("'{}' is not a 4 or 5 digit designation", designation)));
+        }
+        let digit = |idx: usize| (digits.as_bytes()[idx] - b'0') as f32;
+
+        match digits.len() {
+            4 => {
+                let thickness = (digit(2) * 10.0 + digit(3)) / 100.0;
+                let (camber, position) = (digit(0) / 100.0, digit(1) / 10.0);
+                if (camber == 0.0) != (position == 0.0) {
+                    return Err(invalid(format!("'{}' has camber without a camber position", designation)));
+                }
+                if thickness == 0.0 {
+                    return Err(invalid(format!("'{}' has zero thickness", designation)));
+                }
+                Ok(Naca::Four { camber, position, thickness })
+            }
+            5 => {
+                let thickness = (digit(3) * 10.0 + digit(4)) / 100.0;
+                let position = digit(1) as u8;
+                let reflex = match digit(2) {
+                    0.0 => false,
+                    1.0 => true,
+                    _ => return Err(invalid(format!("'{}' third digit must be 0 or 1", designation))),
+                };
+                let supported = if reflex { 2..=5 } else { 1..=5 };
+                if !supported.contains(&position) {
+                    return Err(invalid(format!("'{}' has no tabulated camber line", designation)));
+                }
+                if thickness == 0.0 {
+                    return Err(invalid(format!("'{}' has zero thickness", designation)));
+                }
+                Ok(Naca::Five { design_lift: digit(0) * 0.15, position, reflex, thickness })
+            }
+            _ => Err(invalid(format!("'{}' is not a 4 or 5 digit designation", designation))),
+        }
+    }
+
+    /// builds the outline with <samples> cosine-spaced points per surface, running from the trailing
+    /// edge over the upper surface to the leading edge and back along the lower one
+    pub fn airfoil(&self, samples: usize) -> Airfoil {
+        let samples = samples.max(4);
+        let stations: Vec<f32> =
+            (0..=samples).map(|idx| 0.5 * (1.0 - (PI * idx as f32 / samples as f32).cos())).collect();
+
+        let surface = |x: f32, side: f32| {
+            let (yc, slope) = self.camber(x);
+            let yt = self.half_thickness(x);
+            let theta = slope.atan();
+            Vector::construct(x - side * yt * theta.sin(), yc + side * yt * theta.cos())
+        };
+
+        // the closed trailing edge puts both surfaces on the same last point, so the lower one stops
+        // a station early
+        let mut points: Vec<Vector<f32>> = stations.iter().rev().map(|x| surface(*x, 1.0)).collect();
+        points.extend(stations[1..samples].iter().map(|x| surface(*x, -1.0)));
+
+        Airfoil { name: self.to_string(), points }
+    }
+
+    fn thickness(&self) -> f32 {
+        match self {
+            Naca::Four { thickness, .. } | Naca::Five { thickness, .. } => *thickness,
+        }
+    }
+
+    /// half thickness with the closed trailing edge coefficient, so the outline ends in a point
+    fn half_thickness(&self, x: f32) -> f32 {
+        let t = self.thickness();
+        5.0 * t * (0.2969 * x.sqrt() - 0.1260 * x - 0.3516 * x.powi(2) + 0.2843 * x.powi(3) - 0.1036 * x.powi(4))
+    }
+
+    /// mean line height and slope at <x>
+    fn camber(&self, x: f32) -> (f32, f32) {
+        match *self {
+            Naca::Four { camber: m, position: p, .. } => {
+                if m == 0.0 {
+                    (0.0, 0.0)
+                }
+                else if x < p {
+                    (m / (p * p) * (2.0 * p * x - x * x), 2.0 * m / (p * p) * (p - x))
+                }
+                else {
+                    let q = (1.0 - p) * (1.0 - p);
+                    (m / q * (1.0 - 2.0 * p + 2.0 * p * x - x * x), 2.0 * m / q * (p - x))
+                }
+            }
+            Naca::Five { design_lift, position, reflex: false, .. } => {
+                let (r, k1) = FIVE_STANDARD[position as usize - 1];
+                let k1 = k1 * design_lift / 0.3;
+                if x < r {
+                    (
+                        k1 / 6.0 * (x.powi(3) - 3.0 * r * x * x + r * r * (3.0 - r) * x),
+                        k1 / 6.0 * (3.0 * x * x - 6.0 * r * x + r * r * (3.0 - r)),
+                    )
+                }
+                else {
+                    (k1 * r.powi(3) / 6.0 * (1.0 - x), -k1 * r.powi(3) / 6.0)
+                }
+            }
+            Naca::Five { design_lift, position, reflex: true, .. } => {
+                let (r, k1, k21) = FIVE_REFLEX[position as usize - 2];
+                let k1 = k1 * design_lift / 0.3;
+                let tail = k21 * (1.0 - r).powi(3) + r.powi(3);
+                if x < r {
+                    (
+                        k1 / 6.0 * ((x - r).powi(3) - tail * x + r.powi(3)),
+                        k1 / 6.0 * (3.0 * (x - r).powi(2) - tail),
+                    )
+                }
+                else {
+                    (
+                        k1 / 6.0 * (k21 * (x - r).powi(3) - tail * x + r.powi(3)),
+                        k1 / 6.0 * (3.0 * k21 * (x - r).powi(2) - tail),
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Naca {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let hundredths = |value: f32| (value * 100.0).round() as u32;
+        match *self {
+            Naca::Four { camber, position, thickness } => write!(
+                f,
+                "naca {}{}{:02}",
+                hundredths(camber),
+                (position * 10.0).round() as u32,
+                hundredths(thickness)
+            ),
+            Naca::Five { design_lift, position, reflex, thickness } => write!(
+                f,
+                "naca {}{}{}{:02}",
+                (design_lift / 0.15).round() as u32,
+                position,
+                reflex as u8,
+                hundredths(thickness)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// highest point of <f> over [0, 1], as (x, f(x))
+    fn peak(f: impl Fn(f32) -> f32) -> (f32, f32) {
+        (0..=1000)
+            .map(|idx| idx as f32 / 1000.0)
+            .map(|x| (x, f(x)))
+            .fold((0.0, f32::MIN), |best, next| if next.1 > best.1 { next } else { best })
+    }
+
+    /// thin airfoil quarter chord moment coefficient of the mean line, pi / 4 * (A2 - A1) where
+    /// An = 2 / pi * integral of slope * cos(n theta) over theta in [0, pi]
+    fn quarter_chord_moment(naca: &Naca) -> f32 {
+        let steps = 4000;
+        let dtheta = PI / steps as f32;
+        let coefficient = |n: f32| -> f32 {
+            (0..steps)
+                .map(|idx| dtheta * (idx as f32 + 0.5))
+                .map(|theta| naca.camber(0.5 * (1.0 - theta.cos())).1 * (n * theta).cos() * dtheta)
+                .sum::<f32>()
+                * 2.0
+                / PI
+        };
+        PI / 4.0 * (coefficient(2.0) - coefficient(1.0))
+    }
+
+    #[test]
+    fn designations_parse() {
+        // compared through the designation so float rounding in the parsed fractions doesn't matter
+        let same = |naca: Naca, expected: Naca| assert_eq!(naca.to_string(), expected.to_string());
+        same(Naca::parse("0012").unwrap(), Naca::Four { camber: 0.0, position: 0.0, thickness: 0.12 });
+        same(Naca::parse("NACA 2412").unwrap(), Naca::Four { camber: 0.02, position: 0.4, thickness: 0.12 });
+        same(
+            Naca::parse("naca-23012").unwrap(),
+            Naca::Five { design_lift: 0.3, position: 3, reflex: false, thickness: 0.12 },
+        );
+        assert!(matches!(Naca::parse("23112").unwrap(), Naca::Five { position: 3, reflex: true, .. }));
+
+        for invalid in ["12", "99999", "123456", "2012", "0200", "0000", "21112", "23212", "24x2", ""] {
+            assert!(Naca::parse(invalid).is_err(), "'{}' was accepted", invalid);
+        }
+    }
+
+    #[test]
+    fn naca_0012_is_twelve_percent_thick_at_thirty_percent_chord() {
+        let naca = Naca::parse("0012").unwrap();
+        let (x, half) = peak(|x| naca.half_thickness(x));
+        assert!((half - 0.06).abs() < 5e-4 && (x - 0.3).abs() < 0.01, "{} at {}", half, x);
+        assert!(naca.half_thickness(1.0).abs() < 1e-6);
+
+        // symmetric, so each upper point has a lower twin mirrored in the chord line
+        let points = naca.airfoil(NACA_SAMPLES).points;
+        assert_eq!(points.len(), 2 * NACA_SAMPLES);
+        for idx in 1..NACA_SAMPLES {
+            let (upper, lower) = (points[NACA_SAMPLES - idx], points[NACA_SAMPLES + idx]);
+            assert!((upper.x - lower.x).abs() < 1e-6 && (upper.y + lower.y).abs() < 1e-6);
+        }
+        let top = points.iter().fold(0.0f32, |top, point| top.max(point.y));
+        assert!((top - 0.06).abs() < 1e-3, "{}", top);
+    }
+
+    #[test]
+    fn naca_2412_has_two_percent_camber_at_forty_percent_chord() {
+        let naca = Naca::parse("2412").unwrap();
+        let (x, camber) = peak(|x| naca.camber(x).0);
+        assert!((camber - 0.02).abs() < 1e-5 && (x - 0.4).abs() < 0.002, "{} at {}", camber, x);
+        assert!(naca.camber(0.4).1.abs() < 1e-6);
+        assert!(naca.camber(0.0).0.abs() < 1e-6 && naca.camber(1.0).0.abs() < 1e-6);
+    }
+
+    #[test]
+    fn five_digit_mean_lines_peak_at_their_position() {
+        for designation in ["23012", "23112"] {
+            let naca = Naca::parse(designation).unwrap();
+            let (x, _) = peak(|x| naca.camber(x).0);
+            assert!((x - 0.15).abs() < 0.005, "{} peaks at {}", designation, x);
+            assert!(naca.camber(0.0).0.abs() < 1e-6 && naca.camber(1.0).0.abs() < 1e-6, "{}", designation);
+        }
+
+        // both branches of each mean line meet at r
+        for (idx, (r, _)) in FIVE_STANDARD.iter().enumerate() {
+            let naca = Naca::Five { design_lift: 0.3, position: idx as u8 + 1, reflex: false, thickness: 0.12 };
+            let (below, above) = (naca.camber(r - 1e-4), naca.camber(r + 1e-4));
+            assert!((below.0 - above.0).abs() < 1e-4 && (below.1 - above.1).abs() < 1e-2, "position {}", idx + 1);
+        }
+        for (idx, (r, _, _)) in FIVE_REFLEX.iter().enumerate() {
+            let naca = Naca::Five { design_lift: 0.3, position: idx as u8 + 2, reflex: true, thickness: 0.12 };
+            let (below, above) = (naca.camber(r - 1e-4), naca.camber(r + 1e-4));
+            assert!((below.0 - above.0).abs() < 1e-4 && (below.1 - above.1).abs() < 1e-2, "position {}", idx + 2);
+        }
+    }
+
+    #[test]
+    fn reflexed_mean_lines_cancel_the_pitching_moment() {
+        let standard = quarter_chord_moment(&Naca::parse("23012").unwrap());
+        let reflexed = quarter_chord_moment(&Naca::parse("23112").unwrap());
+        assert!(standard < -0.005, "{}", standard);
+        assert!(reflexed.abs() < 0.1 * standard.abs(), "{} vs {}", reflexed, standard);
+    }
+
+    #[test]
+    fn angle_of_attack_pitches_the_nose_up_about_the_quarter_chord() {
+        let airfoil = Naca::parse("0012").unwrap().airfoil(NACA_SAMPLES);
+        let (x, y, chord, aoa) = (20.0, 30.0, 40.0, 10.0f32);
+        let level = airfoil.placed(x, y, chord, 0.0);
+        let pitched = airfoil.placed(x, y, chord, aoa);
+
+        // the trailing edge is the first point and the leading edge sits halfway round
+        let (trailing, leading) = (pitched.points[0], pitched.points[NACA_SAMPLES]);
+        let level = (level.points[0], level.points[NACA_SAMPLES]);
+        assert_eq!(level, (Vector::construct(x + chord, y), Vector::construct(x, y)));
+
+        let (sin, cos) = aoa.to_radians().sin_cos();
+        let pivot = x + chord * 0.25;
+        let expect = |got: Vector<f32>, want: (f32, f32)| {
+            assert!((got.x - want.0).abs() < 1e-3 && (got.y - want.1).abs() < 1e-3, "{:?} vs {:?}", got, want)
+        };
+        // y points down on the grid, so nose up is towards smaller y
+        expect(leading, (pivot - chord * 0.25 * cos, y - chord * 0.25 * sin));
+        expect(trailing, (pivot + chord * 0.75 * cos, y + chord * 0.75 * sin));
+    }
+    /// writes <points> as a selig loop, closing it on the trailing edge again
+    fn selig(points: &[Vector<f32>]) -> String {
+        let mut text = String::from("TEST SECTION\n");
+        for point in points.iter().chain(points.first()) {
+            text += &format!("{:.6} {:.6}\n", point.x, point.y);
+        }
+        text
+    }
+
+    fn assert_same_outline(a: &[Vector<f32>], b: &[Vector<f32>], tolerance: f32) {
+        assert_eq!(a.len(), b.len());
+        for (p, q) in a.iter().zip(b) {
+            assert!((p.x - q.x).abs() <= tolerance && (p.y - q.y).abs() <= tolerance, "{:?} vs {:?}", p, q);
+        }
+    }
+
+    #[test]
+    fn selig_and_lednicer_layouts_read_the_same() {
+        let section = Naca::parse("2412").unwrap().airfoil(20).points;
+
+        // lednicer lists both surfaces from the leading edge to a shared trailing edge
+        let upper: Vec<Vector<f32>> = section[..=20].iter().rev().copied().collect();
+        let lower: Vec<Vector<f32>> = section[20..].iter().chain(section.first()).copied().collect();
+        let mut lednicer = format!("TEST SECTION\n{}. {}.\n\n", upper.len(), lower.len());
+        for surface in [&upper, &lower] {
+            for point in surface {
+                lednicer += &format!("{:.6} {:.6}\n", point.x, point.y);
+            }
+            lednicer += "\n";
+        }
+
+        let from_selig = Airfoil::parse_dat("selig.dat", &selig(&section)).unwrap();
+        let from_lednicer = Airfoil::parse_dat("lednicer.dat", &lednicer).unwrap();
+        assert_eq!(from_selig.name, "TEST SECTION");
+        assert_eq!(from_selig.points, from_lednicer.points);
+        assert_same_outline(&from_selig.points, &section, 1e-5);
+
+        // without the blank lines, the counts still match the points that follow
+        let packed: String =
+            lednicer.lines().filter(|line| !line.is_empty()).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(Airfoil::parse_dat("packed.dat", &packed).unwrap().points, from_lednicer.points);
+
+        let short = lednicer.replacen(&format!("{}. ", upper.len()), &format!("{}. ", upper.len() + 3), 1);
+        assert!(Airfoil::parse_dat("short.dat", &short).unwrap_err().to_string().contains("header lists"));
+    }
+
+    #[test]
+    fn selig_points_that_look_like_a_lednicer_header_stay_points() {
+        // stored at a 40 unit chord with the trailing edge on (52, 7), so the first row is two whole numbers
+        let section = Naca::parse("0012").unwrap().airfoil(20).points;
+        let stored = Polygon::construct(section.clone()).scaled(40.0, 40.0).translated(12.0, 7.0);
+        assert_eq!(stored.points[0], Vector::construct(52.0, 7.0));
+
+        let airfoil = Airfoil::parse_dat("offset.dat", &selig(&stored.points)).unwrap();
+        assert_same_outline(&airfoil.points, &section, 1e-4);
+    }
+
+    #[test]
+    fn sections_are_normalised_to_unit_chord() {
+        // a 250 unit chord, pitched 8 degrees about a point off the section and moved away from the origin
+        let section = Naca::parse("4415").unwrap().airfoil(30).points;
+        let stored = Polygon::construct(section.clone())
+            .scaled(250.0, 250.0)
+            .rotated(8.0, Vector::construct(-40.0, 10.0))
+            .translated(-75.5, 310.25);
+
+        let airfoil = Airfoil::parse_dat("stored.dat", &selig(&stored.points)).unwrap();
+        assert_same_outline(&airfoil.points, &section, 1e-4);
+        let leading = airfoil.points.iter().fold(f32::MAX, |lowest, point| lowest.min(point.x));
+        let trailing = airfoil.points.iter().fold(f32::MIN, |highest, point| highest.max(point.x));
+        assert!(leading.abs() < 1e-4 && (trailing - 1.0).abs() < 1e-4, "{} to {}", leading, trailing);
+    }
+}
@@ -0,0 +1,518 @@
+//! headless batch runs - steps a simulation with no window and writes the requested outputs to disk
+//!
+//! this is what <fluid run --headless> drives. everything here is plain library code so the same run
+//! can also be scripted from rust
+
+use crate::config;
+use crate::error;
+use crate::export;
+use crate::fluid;
+use crate::render;
+
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use config::{parse_flags, parse_value, Config, VisualMode};
+use error::FluidError;
+use export::{npy, vtk};
+use fluid::Fluid;
+use render::{FieldView, Renderer, Rgba, SoftwareRenderer};
+
+/// every flag understood by a batch run on top of the usual config settings
+pub const FLAGS: [&str; 10] =
+    ["headless", "steps", "time", "out", "every", "interval", "output", "resume", "frame_scale", "frame_speed"];
+
+/// what a batch run can write. each is opt-in through <--output a,b,...>
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    /// one csv row of step, time, step length, max speed, max divergence and the pressure solve's
+    /// iterations and residual every <every> steps
+    Summary,
+
+    /// <checkpoint.bin>, overwritten every <every> steps and once more at the end of the run
+    Checkpoint,
+
+    /// a png of one of the window's visual modes every <every> steps, written to
+    /// <MODE/frame_000123.png> with the step number in the name
+    Frames(VisualMode),
+
+    /// vtk xml image data every <every> steps as <vti/frame_000123.vti>, listed in <fluid.pvd>
+    Vti,
+
+    /// legacy vtk structured points every <every> steps as <vtk/frame_000123.vtk>
+    Vtk,
+
+    /// numpy arrays every <every> steps as <npy/frame_000123_u.npy>, <..._v.npy> and so on
+    Npy,
+
+    /// the same arrays bundled as <npz/frame_000123.npz>
+    Npz,
+}
+
+impl Output {
+    pub fn parse(name: &str) -> Result<Output, FluidError> {
+        match name {
+            "summary" => Ok(Self::Summary),
+            "checkpoint" => Ok(Self::Checkpoint),
+            "gradient" => Ok(Self::Frames(VisualMode::Gradient)),
+            "vector" => Ok(Self::Frames(VisualMode::Vector)),
+            "streamline" => Ok(Self::Frames(VisualMode::Streamline)),
+            "pressure" => Ok(Self::Frames(VisualMode::Pressure)),
+            "vti" => Ok(Self::Vti),
+            "vtk" => Ok(Self::Vtk),
+            "npy" => Ok(Self::Npy),
+            "npz" => Ok(Self::Npz),
+            _ => Err(FluidError::InvalidConfig {
+                key: String::from("output"),
+                reason: format!(
+                    "unknown output '{}', expected one of: summary, checkpoint, gradient, vector, streamline, \
+                     pressure, vti, vtk, npy, npz",
+                    name
+                ),
+            }),
+        }
+    }
+}
+
+/// when a batch run stops
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopAt {
+    /// a fixed number of full steps
+    Steps(usize),
+
+    /// as many steps as it takes for simulated time to reach this many seconds
+    Time(f64),
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub stop: StopAt,
+    pub out: PathBuf,
+    pub every: usize,
+    /// output cadence in simulated seconds, used instead of <every> when set. adaptive steps are
+    /// shortened to land on each output, fixed ones write at the step closest to it
+    pub interval: Option<f64>,
+    pub outputs: Vec<Output>,
+    /// checkpoint to carry on from instead of building a fresh fluid. its own solver settings are
+    /// used, and the stop condition counts from the start of the original run
+    pub resume: Option<PathBuf>,
+
+    /// pixels per cell in frame outputs, independent of the window's cell size
+    pub frame_scale: f32,
+    /// speed at the top of the frame colour map. defaults to the same range the window uses
+    pub frame_speed: Option<f32>,
+}
+
+/// what happened during a run, handed back so the caller can pick an exit status
+#[derive(Debug, Clone)]
+pub struct BatchReport {
+    pub steps: usize,
+    pub time: f64,
+    pub files: Vec<PathBuf>,
+}
+
+impl BatchOptions {
+    /// splits the arguments after <run> into batch options and a config. anything that isn't a
+    /// batch flag is handed to <Config> so the usual <--config FILE> and overrides still apply
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<(BatchOptions, Config), FluidError> {
+        let mut args: Vec<String> = args.into_iter().collect();
+        // --headless is a bare switch, the only flag without a value
+        let headless: bool = args.iter().any(|arg| arg == "--headless");
+        args.retain(|arg| arg != "--headless");
+        if !headless {
+            return Err(FluidError::InvalidConfig {
+                key: String::from("headless"),
+                reason: String::from("batch runs need --headless"),
+            });
+        }
+
+        let mut steps: Option<usize> = None;
+        let mut time: Option<f64> = None;
+        let mut every: Option<usize> = None;
+        let mut options = BatchOptions {
+            stop: StopAt::Steps(0),
+            out: PathBuf::from("output"),
+            every: 1,
+            interval: None,
+            outputs: vec![Output::Summary],
+            resume: None,
+            frame_scale: 1.0,
+            frame_speed: None,
+        };
+        let mut config_args: Vec<String> = Vec::new();
+
+        for (key, value) in parse_flags(args)? {
+            match key.as_str() {
+                "steps" => steps = Some(parse_value(&key, &value)?),
+                "time" => time = Some(parse_value(&key, &value)?),
+                "out" => options.out = PathBuf::from(value),
+                "resume" => options.resume = Some(PathBuf::from(value)),
+                "frame_scale" => options.frame_scale = parse_value(&key, &value)?,
+                "frame_speed" => options.frame_speed = Some(parse_value(&key, &value)?),
+                "every" => every = Some(parse_value(&key, &value)?),
+                "interval" => options.interval = Some(parse_value(&key, &value)?),
+                "output" => {
+                    options.outputs = value.split(',').map(|name| Output::parse(name.trim())).collect::<Result<_, _>>()?
+                }
+                _ => config_args.extend([format!("--{}", key), value]),
+            }
+        }
+
+        options.stop = match (steps, time) {
+            (Some(steps), None) => StopAt::Steps(steps),
+            (None, Some(time)) if time.is_finite() && time >= 0.0 => StopAt::Time(time),
+            (None, Some(time)) => {
+                return Err(FluidError::InvalidConfig {
+                    key: String::from("time"),
+                    reason: format!("must be zero or positive, got {}", time),
+                })
+            }
+            _ => {
+                return Err(FluidError::InvalidConfig {
+                    key: String::from("steps"),
+                    reason: String::from("exactly one of --steps or --time is required"),
+                })
+            }
+        };
+        if !(options.frame_scale.is_finite() && options.frame_scale > 0.0) {
+            return Err(FluidError::InvalidConfig {
+                key: String::from("frame_scale"),
+                reason: format!("must be a positive number of pixels per cell, got {}", options.frame_scale),
+            });
+        }
+        if options.frame_speed.is_some_and(|speed| !(speed.is_finite() && speed > 0.0)) {
+            return Err(FluidError::InvalidConfig {
+                key: String::from("frame_speed"),
+                reason: String::from("must be a positive speed"),
+            });
+        }
+        if every.is_some() && options.interval.is_some() {
+            return Err(FluidError::InvalidConfig {
+                key: String::from("interval"),
+                reason: String::from("--every and --interval can't both be given"),
+            });
+        }
+        options.every = every.unwrap_or(options.every);
+        if options.every == 0 {
+            return Err(FluidError::InvalidConfig {
+                key: String::from("every"),
+                reason: String::from("output cadence must be at least 1 step"),
+            });
+        }
+        if options.interval.is_some_and(|interval| !(interval.is_finite() && interval > 0.0)) {
+            return Err(FluidError::InvalidConfig {
+                key: String::from("interval"),
+                reason: String::from("must be a positive number of seconds"),
+            });
+        }
+
+        Ok((options, Config::from_args(config_args)?))
+    }
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> FluidError {
+    FluidError::Io { path: path.display().to_string(), reason: err.to_string() }
+}
+
+/// builds the fluid described by the options and steps it to completion, writing outputs as it goes.
+/// fails if an output can't be written or the velocities stop being finite
+pub fn run(options: &BatchOptions, config: &Config) -> Result<BatchReport, FluidError> {
+    let mut fluid: Fluid = match &options.resume {
+        // no warm up for a resumed run, it would knock it off the path the original was on
+        Some(path) => Fluid::from_checkpoint(&path.to_string_lossy())?,
+        None => {
+            let mut fluid: Fluid = Fluid::from_config(config)?;
+            // same warm up as the interactive loop, projection only so the clock doesn't move
+            for _ in 0..3 {
+                fluid.update_fluid(true, false, false, false);
+            }
+            fluid
+        }
+    };
+
+    fs::create_dir_all(&options.out).map_err(|err| io_error(&options.out, err))?;
+    let mut report = BatchReport { steps: 0, time: 0.0, files: Vec::new() };
+
+    let mut summary: Option<BufWriter<fs::File>> = None;
+    if options.outputs.contains(&Output::Summary) {
+        let path = options.out.join("summary.csv");
+        // a resumed run carries on the summary it left behind, minus any rows written past the checkpoint
+        let earlier: Vec<String> = match options.resume {
+            Some(_) => summary_rows(&path, fluid.step)?,
+            None => Vec::new(),
+        };
+        let file = fs::File::create(&path).map_err(|err| io_error(&path, err))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "step,time,dt,max_speed,max_divergence,iterations,residual")
+            .map_err(|err| io_error(&path, err))?;
+        for row in earlier {
+            writeln!(writer, "{}", row).map_err(|err| io_error(&path, err))?;
+        }
+        summary = Some(writer);
+        report.files.push(path);
+    }
+
+    let frames: Vec<VisualMode> = options
+        .outputs
+        .iter()
+        .filter_map(|output| match output {
+            Output::Frames(mode) => Some(*mode),
+            _ => None,
+        })
+        .collect();
+    for mode in &frames {
+        let dir = options.out.join(mode.name());
+        fs::create_dir_all(&dir).map_err(|err| io_error(&dir, err))?;
+        report.files.push(dir);
+    }
+
+    let vti: bool = options.outputs.contains(&Output::Vti);
+    let legacy: bool = options.outputs.contains(&Output::Vtk);
+    let arrays: bool = options.outputs.contains(&Output::Npy);
+    let bundles: bool = options.outputs.contains(&Output::Npz);
+    for (wanted, name) in [(vti, "vti"), (legacy, "vtk"), (arrays, "npy"), (bundles, "npz")] {
+        if wanted {
+            let dir = options.out.join(name);
+            fs::create_dir_all(&dir).map_err(|err| io_error(&dir, err))?;
+            report.files.push(dir);
+        }
+    }
+    let pvd = options.out.join("fluid.pvd");
+    // the same goes for the collection, so paraview still sees the whole run as one series
+    let mut series: Vec<(f64, PathBuf)> = match options.resume {
+        Some(_) if vti => vtk::read_pvd(&pvd)?.into_iter().filter(|(time, _)| *time <= fluid.time).collect(),
+        _ => Vec::new(),
+    };
+
+    let checkpoint: Option<PathBuf> =
+        options.outputs.contains(&Output::Checkpoint).then(|| options.out.join("checkpoint.bin"));
+
+    // outputs already behind a resumed run, counted the same way they would have been written. a fresh
+    // run has none - its <dt> is only the configured one and says nothing about how far it got
+    let mut written: f64 = match options.interval {
+        Some(interval) if fluid.step > 0 => ((fluid.time + fluid.dt as f64 * 0.5) / interval).floor(),
+        _ => 0.0,
+    };
+
+    while !finished(&fluid, options.stop) {
+        let next_output: Option<f64> = options.interval.map(|interval| (written + 1.0) * interval);
+        fluid.next_stop = match (next_output, options.stop) {
+            (Some(output), StopAt::Time(time)) => Some(output.min(time)),
+            (Some(output), StopAt::Steps(_)) => Some(output),
+            (None, StopAt::Time(time)) => Some(time),
+            (None, StopAt::Steps(_)) => None,
+        };
+        fluid.update_fluid(true, true, true, true);
+
+        if !fluid.is_finite() {
+            return Err(FluidError::Diverged { step: fluid.step, time: fluid.time });
+        }
+
+        let due: bool = match (options.interval, next_output) {
+            (Some(interval), Some(output)) if reached(&fluid, output) => {
+                // a fixed step longer than the interval passes several outputs at once, they're one frame
+                while reached(&fluid, (written + 1.0) * interval) {
+                    written += 1.0;
+                }
+                true
+            }
+            (Some(_), _) => false,
+            (None, _) => fluid.step.is_multiple_of(options.every),
+        };
+        if due {
+            if let Some(writer) = summary.as_mut() {
+                writeln!(
+                    writer,
+                    "{},{:.6},{},{},{},{},{}",
+                    fluid.step,
+                    fluid.time,
+                    fluid.dt,
+                    fluid.max_speed(),
+                    fluid.max_divergence(),
+                    fluid.solve.iterations,
+                    fluid.solve.residual
+                )
+                .map_err(|err| io_error(&options.out.join("summary.csv"), err))?;
+            }
+            if let Some(path) = &checkpoint {
+                fluid.save_checkpoint(&path.to_string_lossy())?;
+            }
+            for mode in &frames {
+                write_frame(&fluid, options, mode)?;
+            }
+            if vti {
+                let path = options.out.join("vti").join(format!("frame_{:06}.vti", fluid.step));
+                vtk::write_vti(&fluid, &path)?;
+                // rewritten every time so a run that dies part way still leaves a loadable series
+                series.push((fluid.time, path));
+                vtk::write_pvd(&pvd, &series)?;
+            }
+            if legacy {
+                vtk::write_legacy(&fluid, &options.out.join("vtk").join(format!("frame_{:06}.vtk", fluid.step)))?;
+            }
+            if arrays {
+                npy::write_npy(&fluid, &options.out.join("npy"), &format!("frame_{:06}", fluid.step))?;
+            }
+            if bundles {
+                npy::write_npz(&fluid, &options.out.join("npz").join(format!("frame_{:06}.npz", fluid.step)))?;
+            }
+        }
+    }
+
+    if let Some(mut writer) = summary {
+        writer.flush().map_err(|err| io_error(&options.out.join("summary.csv"), err))?;
+    }
+
+    if vti {
+        report.files.push(pvd);
+    }
+    if let Some(path) = checkpoint {
+        fluid.save_checkpoint(&path.to_string_lossy())?;
+        report.files.push(path);
+    }
+
+    report.steps = fluid.step;
+    report.time = fluid.time;
+    Ok(report)
+}
+
+/// rows of an existing summary up to and including <step>, without the header. none if there isn't one
+fn summary_rows(path: &Path, step: usize) -> Result<Vec<String>, FluidError> {
+    let text: String = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(io_error(path, err)),
+    };
+
+    Ok(text
+        .lines()
+        .skip(1)
+        .filter(|row| {
+            let row_step = row.split(',').next().and_then(|first| first.parse::<usize>().ok());
+            row_step.is_some_and(|row_step| row_step <= step)
+        })
+        .map(String::from)
+        .collect())
+}
+
+/// renders one visual mode off screen, with the same background the window clears to
+fn write_frame(fluid: &Fluid, options: &BatchOptions, mode: &VisualMode) -> Result<(), FluidError> {
+    let mut view = FieldView::with_cell_size(fluid, options.frame_scale);
+    if let Some(speed) = options.frame_speed {
+        view.colour_max = speed;
+    }
+
+    let mut renderer = SoftwareRenderer::for_view(&view);
+    renderer.clear(Rgba::from_hex(0x121212));
+    renderer.visual(&view, mode);
+
+    let path = options.out.join(mode.name()).join(format!("frame_{:06}.png", fluid.step));
+    renderer.write_png(&path.to_string_lossy())
+}
+
+fn finished(fluid: &Fluid, stop: StopAt) -> bool {
+    match stop {
+        StopAt::Steps(steps) => fluid.step >= steps,
+        StopAt::Time(time) => reached(fluid, time),
+    }
+}
+
+/// whether simulated time is at <time>, give or take half the last step so float accumulation can't
+/// tack an extra step on
+fn reached(fluid: &Fluid, time: f64) -> bool {
+    fluid.time + fluid.dt as f64 * 0.5 >= time
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, sized};
+
+    fn options(out: &Path, steps: usize, resume: Option<PathBuf>) -> BatchOptions {
+        BatchOptions {
+            stop: StopAt::Steps(steps),
+            out: out.to_path_buf(),
+            every: 1,
+            interval: None,
+            outputs: vec![Output::Summary, Output::Checkpoint, Output::Vti],
+            resume,
+            frame_scale: 1.0,
+            frame_speed: None,
+        }
+    }
+
+    #[test]
+    fn resuming_into_the_same_directory_continues_its_outputs() {
+        let out: PathBuf = scratch_dir("resume");
+        let config: Config = sized(30, 20);
+
+        run(&options(&out, 3, None), &config).unwrap();
+        run(&options(&out, 6, Some(out.join("checkpoint.bin"))), &config).unwrap();
+
+        let summary: String = fs::read_to_string(out.join("summary.csv")).unwrap();
+        let steps: Vec<&str> = summary.lines().skip(1).map(|row| row.split(',').next().unwrap()).collect();
+        assert_eq!(steps, ["1", "2", "3", "4", "5", "6"]);
+        assert_eq!(vtk::read_pvd(&out.join("fluid.pvd")).unwrap().len(), 6);
+
+        fs::remove_dir_all(&out).unwrap();
+    }
+    #[test]
+    fn adaptive_steps_keep_within_the_cfl_and_delta_t() {
+        for (cfl, limited) in [(0.5, true), (1.5, true), (100.0, false)] {
+            let mut config: Config = sized(30, 20);
+            config.cfl = cfl;
+            let mut fluid: Fluid = Fluid::from_config(&config).unwrap();
+
+            for _ in 0..10 {
+                let (speed, dt) = (fluid.max_speed(), fluid.next_step_size());
+                assert!(dt <= fluid.delta_t);
+                // the fastest face crosses at most <cfl> cells
+                assert!(dt * speed / fluid.grid_size <= cfl * (1.0 + 1e-6), "cfl {}: {} cells", cfl, dt * speed);
+                if limited {
+                    assert!(dt < fluid.delta_t, "cfl {} never shortened the step", cfl);
+                }
+                else {
+                    assert_eq!(dt, fluid.delta_t);
+                }
+                fluid.update_fluid(true, true, true, true);
+                assert_eq!(fluid.dt, dt);
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_steps_land_on_every_interval() {
+        let out: PathBuf = scratch_dir("interval");
+        let mut config: Config = sized(30, 20);
+        config.cfl = 1.0;
+        let mut options = options(&out, 0, None);
+        options.stop = StopAt::Time(0.3);
+        options.interval = Some(0.05);
+        options.outputs = vec![Output::Summary];
+
+        let report: BatchReport = run(&options, &config).unwrap();
+        let summary: String = fs::read_to_string(out.join("summary.csv")).unwrap();
+        let rows: Vec<Vec<&str>> = summary.lines().skip(1).map(|row| row.split(',').collect()).collect();
+
+        // one row per interval, each exactly on its mark, and several cfl-limited steps between rows
+        let times: Vec<&str> = rows.iter().map(|row| row[1]).collect();
+        assert_eq!(times, ["0.050000", "0.100000", "0.150000", "0.200000", "0.250000", "0.300000"]);
+        assert!(report.steps > 2 * rows.len(), "only {} steps", report.steps);
+        assert!((report.time - 0.3).abs() < 1e-9, "stopped at {}", report.time);
+
+        // stopping half way and resuming writes the same marks, none twice
+        options.stop = StopAt::Time(0.15);
+        options.outputs = vec![Output::Summary, Output::Checkpoint];
+        run(&options, &config).unwrap();
+        options.stop = StopAt::Time(0.3);
+        options.resume = Some(out.join("checkpoint.bin"));
+        run(&options, &config).unwrap();
+        let resumed: String = fs::read_to_string(out.join("summary.csv")).unwrap();
+        assert_eq!(resumed, summary);
+
+        fs::remove_dir_all(&out).unwrap();
+    }
+}
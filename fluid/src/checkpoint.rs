@@ -0,0 +1,415 @@
+//! binary checkpoints of a whole simulation
+//!
+//! a checkpoint holds everything a step reads - velocities, vorticity, pressure, the element grid with
+//! source and clone payloads, the boundary list in the order boundary conditions are enforced, the
+//! solver parameters and the clock - so a restored fluid steps on exactly as the original would have.
+//!
+//! layout, all little endian:
+//!
+//! ```text
+//! "FLUIDCKP"  u32 version  header fields  u32 crc32(everything so far)
+//! then sections of:  [u8; 4] tag  u64 length  payload  u32 crc32(payload)
+//! ```
+//!
+//! the header is x, y, iters, step, warm_start, the residual norm (0 max, 1 rms), the solver (0 gauss
+//! seidel, 1 pcg, 2 multigrid, 3 red-black), the multigrid coarsening (0 conservative, 1 majority), the
+//! advection scheme (0 semi-lagrangian, 1 maccormack, 2 bfecc) and the backtrace integrator (0 euler, 1
+//! rk2, 2 rk3, 3 rk4) as u64, then overrelaxation, delta_t, cfl, the last step's dt, source_velocity,
+//! grid_size, epsilon, density, tolerance, visual_modifier and cell_size as f32 and time as f64.
+//! sections are <U___>, <V___>, <VORT> and <PRES> (raw f32 grids), <ELEM> (one tag byte per cell, then
+//! vx vy as f32 for sources or dx dy as i64 for clones) and <BNDS> (x y pairs as u64)
+
+use crate::clone;
+use crate::config;
+use crate::error;
+use crate::field;
+use crate::fluid;
+use crate::scene;
+use crate::source;
+use crate::utils;
+
+use std::fs;
+
+use clone::Clone;
+use config::{Advection, Backtrace, Coarsening, Config, ResidualNorm, Solver};
+use error::FluidError;
+use field::Field2D;
+use fluid::{Ele, Fluid};
+use scene::{Directive, Scene};
+use source::Source;
+use utils::{crc32, Vector};
+
+pub const MAGIC: &[u8; 8] = b"FLUIDCKP";
+
+/// bumped whenever the layout changes. older or newer checkpoints are refused rather than misread
+pub const VERSION: u32 = 8;
+
+const SECTIONS: [&[u8; 4]; 6] = [b"U___", b"V___", b"VORT", b"PRES", b"ELEM", b"BNDS"];
+
+pub fn save(fluid: &Fluid, path: &str) -> Result<(), FluidError> {
+    fs::write(path, encode(fluid)).map_err(|err| FluidError::Io { path: path.to_string(), reason: err.to_string() })
+}
+
+pub fn load(path: &str) -> Result<Fluid, FluidError> {
+    let bytes: Vec<u8> =
+        fs::read(path).map_err(|err| FluidError::Io { path: path.to_string(), reason: err.to_string() })?;
+    decode(&bytes).map_err(|reason| FluidError::Checkpoint { path: path.to_string(), reason })
+}
+
+pub fn encode(fluid: &Fluid) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    let norm: usize = match fluid.residual {
+        ResidualNorm::Max => 0,
+        ResidualNorm::Rms => 1,
+    };
+    let solver: usize = match fluid.solver {
+        Solver::GaussSeidel => 0,
+        Solver::Pcg => 1,
+        Solver::Multigrid => 2,
+        Solver::RedBlack => 3,
+    };
+    let advection: usize = match fluid.advection {
+        Advection::SemiLagrangian => 0,
+        Advection::MacCormack => 1,
+        Advection::Bfecc => 2,
+    };
+    let backtrace: usize = match fluid.backtrace {
+        Backtrace::Euler => 0,
+        Backtrace::Rk2 => 1,
+        Backtrace::Rk3 => 2,
+        Backtrace::Rk4 => 3,
+    };
+    let coarsening: usize = match fluid.coarsening {
+        Coarsening::Conservative => 0,
+        Coarsening::Majority => 1,
+    };
+    let warm_start: usize = fluid.warm_start as usize;
+    let codes = [norm, solver, coarsening, advection, backtrace];
+    for value in [fluid.x, fluid.y, fluid.iters, fluid.step, warm_start].into_iter().chain(codes) {
+        bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+    for value in [
+        fluid.overrelaxation,
+        fluid.delta_t,
+        fluid.cfl,
+        fluid.dt,
+        fluid.source_velocity,
+        fluid.grid_size,
+        fluid.epsilon,
+        fluid.density,
+        fluid.tolerance,
+        fluid.visual_modifier,
+        fluid.cell_size,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(&fluid.time.to_le_bytes());
+    bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+
+    let floats = |field: &Field2D<f32>| field.as_slice().iter().flat_map(|value| value.to_le_bytes()).collect();
+
+    let mut elements: Vec<u8> = Vec::with_capacity(fluid.x * fluid.y);
+    for ele in fluid.element.as_slice() {
+        match ele {
+            Ele::Fluid => elements.push(0),
+            Ele::Static => elements.push(1),
+            Ele::Source(source) => {
+                elements.push(2);
+                elements.extend_from_slice(&source.velocity.x.to_le_bytes());
+                elements.extend_from_slice(&source.velocity.y.to_le_bytes());
+            }
+            Ele::Clone(clone) => {
+                elements.push(3);
+                elements.extend_from_slice(&(clone.master.x as i64).to_le_bytes());
+                elements.extend_from_slice(&(clone.master.y as i64).to_le_bytes());
+            }
+        }
+    }
+
+    let boundaries: Vec<u8> = fluid
+        .boundaries_dep
+        .iter()
+        .flat_map(|cell| [(cell.x as u64).to_le_bytes(), (cell.y as u64).to_le_bytes()])
+        .flatten()
+        .collect();
+
+    let payloads: [Vec<u8>; 6] = [
+        floats(&fluid.u),
+        floats(&fluid.v),
+        floats(&fluid.vorticity),
+        floats(&fluid.pressure),
+        elements,
+        boundaries,
+    ];
+    for (tag, payload) in SECTIONS.iter().zip(payloads) {
+        bytes.extend_from_slice(*tag);
+        bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+    }
+
+    bytes
+}
+
+/// cursor over checkpoint bytes that turns running off the end into an error
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.bytes.len());
+        let end = end.ok_or_else(|| String::from("file ends early, the checkpoint is truncated"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<usize, String> {
+        usize::try_from(u64::from_le_bytes(self.array()?)).map_err(|_| String::from("value too large for this platform"))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Fluid, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(String::from("not a fluid checkpoint"));
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(format!("checkpoint is version {}, this build reads version {}", version, VERSION));
+    }
+
+    let mut config = Config::new();
+    config.x = reader.u64()?;
+    config.y = reader.u64()?;
+    config.iters = reader.u64()?;
+    let step = reader.u64()?;
+    config.warm_start = reader.u64()? != 0;
+    config.residual = match reader.u64()? {
+        0 => ResidualNorm::Max,
+        1 => ResidualNorm::Rms,
+        other => return Err(format!("unknown residual norm {}", other)),
+    };
+    config.solver = match reader.u64()? {
+        0 => Solver::GaussSeidel,
+        1 => Solver::Pcg,
+        2 => Solver::Multigrid,
+        3 => Solver::RedBlack,
+        other => return Err(format!("unknown pressure solver {}", other)),
+    };
+    config.coarsening = match reader.u64()? {
+        0 => Coarsening::Conservative,
+        1 => Coarsening::Majority,
+        other => return Err(format!("unknown multigrid coarsening {}", other)),
+    };
+    config.advection = match reader.u64()? {
+        0 => Advection::SemiLagrangian,
+        1 => Advection::MacCormack,
+        2 => Advection::Bfecc,
+        other => return Err(format!("unknown advection scheme {}", other)),
+    };
+    config.backtrace = match reader.u64()? {
+        0 => Backtrace::Euler,
+        1 => Backtrace::Rk2,
+        2 => Backtrace::Rk3,
+        3 => Backtrace::Rk4,
+        other => return Err(format!("unknown backtrace integrator {}", other)),
+    };
+    config.overrelaxation = reader.f32()?;
+    config.delta_t = reader.f32()?;
+    config.cfl = reader.f32()?;
+    let dt = reader.f32()?;
+    config.source_velocity = reader.f32()?;
+    config.grid_size = reader.f32()?;
+    config.epsilon = reader.f32()?;
+    config.density = reader.f32()?;
+    config.tolerance = reader.f32()?;
+    config.visual_modifier = reader.f32()?;
+    config.cell_size = reader.f32()?;
+    let time = f64::from_le_bytes(reader.array()?);
+
+    let header_end = reader.pos;
+    if reader.u32()? != crc32(&bytes[..header_end]) {
+        return Err(String::from("header checksum mismatch, the checkpoint is corrupt"));
+    }
+    if config.x.checked_mul(config.y).is_none_or(|cells| cells == 0 || cells > bytes.len()) {
+        return Err(format!("grid size {}x{} doesn't fit the file", config.x, config.y));
+    }
+    // a matching crc only says the header wasn't damaged, not that the build that wrote it kept to the
+    // ranges this one assumes
+    config.validate().map_err(|err| err.to_string())?;
+    if !(dt.is_finite() && dt > 0.0 && time.is_finite()) {
+        return Err(format!("step length {} or time {} is out of range", dt, time));
+    }
+
+    let mut fluid: Fluid = Fluid::construct(&config);
+    fluid.step = step;
+    fluid.time = time;
+    fluid.dt = dt;
+
+    for tag in SECTIONS {
+        if reader.take(4)? != tag {
+            return Err(format!("expected the {} section", String::from_utf8_lossy(tag)));
+        }
+        let len = reader.u64()?;
+        let payload = reader.take(len)?;
+        if reader.u32()? != crc32(payload) {
+            return Err(format!("{} checksum mismatch, the checkpoint is corrupt", String::from_utf8_lossy(tag)));
+        }
+
+        let section = Reader { bytes: payload, pos: 0 };
+        match tag {
+            b"U___" => read_floats(section, &mut fluid.u)?,
+            b"V___" => read_floats(section, &mut fluid.v)?,
+            b"VORT" => read_floats(section, &mut fluid.vorticity)?,
+            b"PRES" => read_floats(section, &mut fluid.pressure)?,
+            b"ELEM" => read_elements(section, &mut fluid.element)?,
+            _ => read_boundaries(section, &mut fluid)?,
+        }
+    }
+
+    // resets rebuild the checkpointed geometry, not whatever scene the original run started from
+    let (mask, rules) = fluid.geometry_mask();
+    fluid.scene = Some(Scene { directives: vec![Directive::Mask { mask, rules }] });
+
+    Ok(fluid)
+}
+
+fn read_floats(mut section: Reader, field: &mut Field2D<f32>) -> Result<(), String> {
+    if section.bytes.len() != field.as_slice().len() * 4 {
+        return Err(String::from("field section doesn't match the grid size"));
+    }
+    for value in field.as_mut_slice() {
+        *value = section.f32()?;
+    }
+    Ok(())
+}
+
+fn read_elements(mut section: Reader, element: &mut Field2D<Ele>) -> Result<(), String> {
+    for ele in element.as_mut_slice() {
+        *ele = match section.take(1)?[0] {
+            0 => Ele::Fluid,
+            1 => Ele::Static,
+            2 => Ele::Source(Source::construct(section.f32()?, section.f32()?)),
+            3 => {
+                let dx = i64::from_le_bytes(section.array()?) as isize;
+                let dy = i64::from_le_bytes(section.array()?) as isize;
+                Ele::Clone(Clone::construct(dx, dy))
+            }
+            other => return Err(format!("unknown element tag {}", other)),
+        };
+    }
+    if section.pos != section.bytes.len() {
+        return Err(String::from("element section is longer than the grid"));
+    }
+    Ok(())
+}
+
+fn read_boundaries(mut section: Reader, fluid: &mut Fluid) -> Result<(), String> {
+    while section.pos < section.bytes.len() {
+        let (x, y) = (section.u64()?, section.u64()?);
+        if x >= fluid.x || y >= fluid.y {
+            return Err(format!("boundary cell ({}, {}) is outside the grid", x, y));
+        }
+        fluid.boundaries_dep.push(Vector::construct(x, y));
+        fluid.boundaries.insert(Vector::construct(x, y));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sized, stepped_fluid};
+
+    /// every setting a checkpoint stores moved off its default
+    fn running_fluid(steps: usize) -> Fluid {
+        let mut config: Config = sized(40, 30);
+        config.warm_start = true;
+        config.overrelaxation = 1.7;
+        config.solver = Solver::Pcg;
+        config.coarsening = Coarsening::Majority;
+        config.advection = Advection::MacCormack;
+        config.backtrace = Backtrace::Rk2;
+        config.cfl = 1.5;
+        stepped_fluid(&config, steps)
+    }
+
+    fn assert_same_state(a: &Fluid, b: &Fluid) {
+        assert_eq!((a.step, a.time, a.dt), (b.step, b.time, b.dt));
+        assert_eq!(a.u, b.u);
+        assert_eq!(a.v, b.v);
+        assert_eq!(a.vorticity, b.vorticity);
+        assert_eq!(a.pressure, b.pressure);
+        assert_eq!(a.element, b.element);
+        assert_eq!(a.boundaries_dep, b.boundaries_dep);
+    }
+
+    #[test]
+    fn decode_restores_what_encode_saved() {
+        let fluid: Fluid = running_fluid(4);
+        let restored: Fluid = decode(&encode(&fluid)).unwrap();
+
+        assert_same_state(&fluid, &restored);
+        assert_eq!((restored.solver, restored.coarsening), (Solver::Pcg, Coarsening::Majority));
+        assert_eq!((restored.advection, restored.backtrace), (Advection::MacCormack, Backtrace::Rk2));
+        assert_eq!((restored.warm_start, restored.cfl, restored.delta_t), (true, 1.5, fluid.delta_t));
+    }
+
+    #[test]
+    fn corrupt_checkpoints_are_refused() {
+        let bytes: Vec<u8> = encode(&running_fluid(1));
+
+        let mut header = bytes.clone();
+        header[MAGIC.len() + 8] ^= 1;
+        assert!(decode(&header).unwrap_err().contains("checksum"));
+
+        let mut payload = bytes.clone();
+        let last = payload.len() - 5;
+        payload[last] ^= 1;
+        assert!(decode(&payload).unwrap_err().contains("checksum"));
+
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn resuming_matches_an_uninterrupted_run() {
+        let straight: Fluid = running_fluid(8);
+
+        let mut resumed: Fluid = decode(&encode(&running_fluid(3))).unwrap();
+        for _ in 3..8 {
+            resumed.update_fluid(true, true, true, true);
+        }
+
+        assert_same_state(&straight, &resumed);
+    }
+    #[test]
+    fn settings_outside_the_valid_ranges_are_refused() {
+        let mut overrelaxed: Fluid = running_fluid(1);
+        overrelaxed.overrelaxation = 2.5;
+        assert!(decode(&encode(&overrelaxed)).unwrap_err().contains("overrelaxation"));
+
+        let tiny: Fluid = Fluid::construct(&sized(2, 5));
+        assert!(decode(&encode(&tiny)).unwrap_err().contains("3x3"));
+
+        let mut stalled: Fluid = running_fluid(1);
+        stalled.dt = 0.0;
+        assert!(decode(&encode(&stalled)).is_err());
+    }
+}
@@ -1,4 +1,11 @@
+use crate::error;
+use crate::presets;
+
+#[cfg(feature = "gui")]
 use macroquad::prelude::*;
+use std::{fs, str::FromStr};
+
+use error::FluidError;
 
 static SCALE_FACTOR: usize = 25;
 static WIDTH: usize = 20 * SCALE_FACTOR;
@@ -11,9 +18,13 @@ static SOURCE_V: f32 = 145.0;
 static VISUAL_MOD: f32 = 2.0;
 static GRID_SIZE: f32 = 2.0;
 static VORT_CONF_EPSILON: f32 = 0.3;
+static DENSITY: f32 = 1000.0;
+static TOLERANCE: f32 = 0.0;
+static CFL: f32 = 0.0;
 
 /// used to pass all simulation configuration information from <config> module into main to
 /// construct fluid
+#[derive(Clone)]
 pub struct Config {
     pub x: usize,
     pub y: usize,
@@ -21,10 +32,43 @@ pub struct Config {
     pub cell_size: f32,
     pub iters: usize,
     pub delta_t: f32,
+    /// largest distance in cells any face may be carried in one step. 0 always steps <delta_t>,
+    /// otherwise each step is as long as the fastest face allows and <delta_t> is only the cap
+    pub cfl: f32,
     pub source_velocity: f32,
     pub visual_modifier: f32,
     pub grid_size: f32,
     pub epsilon: f32,
+    /// only scales the recovered pressure, the velocities don't depend on it
+    pub density: f32,
+    /// start each projection from the last step's pressure instead of from zero. pays off with
+    /// overrelaxation well below 2 - close to 2 the error short solves leave behind can build up
+    pub warm_start: bool,
+    /// projection stops early once the residual divergence is at or below this. 0 always runs <iters>
+    /// sweeps, otherwise <iters> is only the cap
+    pub tolerance: f32,
+    /// how the residual is measured against <tolerance>
+    pub residual: ResidualNorm,
+    /// pressure solver used by projection
+    pub solver: Solver,
+    /// how the multigrid solver merges cells into coarser levels
+    pub coarsening: Coarsening,
+    /// worker threads for the red_black solver, 0 for one per core. results don't depend on it
+    pub threads: usize,
+    /// velocity advection scheme
+    pub advection: Advection,
+    /// integrator advection traces departure points back with
+    pub backtrace: Backtrace,
+    /// scene file to build the boundaries from, the built-in layout when missing
+    pub scene: Option<String>,
+    /// name of a built-in scene, see <presets>
+    pub preset: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
 }
 
 impl Config {
@@ -36,19 +80,252 @@ impl Config {
             cell_size: CELL_SIZE,
             iters: ITERS,
             delta_t: DELTA_T,
+            cfl: CFL,
             source_velocity: SOURCE_V,
             visual_modifier: VISUAL_MOD,
             grid_size: GRID_SIZE,
             epsilon: VORT_CONF_EPSILON,
+            density: DENSITY,
+            warm_start: false,
+            tolerance: TOLERANCE,
+            residual: ResidualNorm::new(),
+            solver: Solver::new(),
+            coarsening: Coarsening::new(),
+            threads: 0,
+            advection: Advection::new(),
+            backtrace: Backtrace::new(),
+            scene: None,
+            preset: None,
+        }
+    }
+
+    /// loads a config file on top of the built-in defaults and validates the result
+    pub fn from_file(path: &str) -> Result<Config, FluidError> {
+        let mut config: Config = Config::new();
+        config.apply_file(path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// builds a config from command-line flags. <--config FILE> is read first wherever it appears,
+    /// then every other <--key value> or <--key=value> flag overrides what the file set
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Config, FluidError> {
+        let flags: Vec<(String, String)> = parse_flags(args)?;
+        let mut config: Config = Config::new();
+
+        for (_, path) in flags.iter().filter(|(key, _)| key == "config") {
+            config.apply_file(path)?;
+        }
+        for (key, value) in flags.iter().filter(|(key, _)| key != "config") {
+            config.set(key, value)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// reads <key = value> lines from a file. blank lines and anything after a <#> are ignored
+    pub fn apply_file(&mut self, path: &str) -> Result<(), FluidError> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|err| FluidError::Io { path: path.to_string(), reason: err.to_string() })?;
+
+        for (line, key, value) in parse_pairs(path, &text)? {
+            self.set(&key, &value).map_err(|err| FluidError::Parse {
+                path: path.to_string(),
+                line,
+                reason: err.to_string(),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// sets a single field by name. no range checking happens here - see <validate()>
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), FluidError> {
+        match key {
+            "width" => self.x = parse_value(key, value)?,
+            "height" => self.y = parse_value(key, value)?,
+            "overrelaxation" => self.overrelaxation = parse_value(key, value)?,
+            "cell_size" => self.cell_size = parse_value(key, value)?,
+            "iters" => self.iters = parse_value(key, value)?,
+            "delta_t" => self.delta_t = parse_value(key, value)?,
+            "cfl" => self.cfl = parse_value(key, value)?,
+            "source_velocity" => self.source_velocity = parse_value(key, value)?,
+            "visual_modifier" => self.visual_modifier = parse_value(key, value)?,
+            "grid_size" => self.grid_size = parse_value(key, value)?,
+            "epsilon" => self.epsilon = parse_value(key, value)?,
+            "density" => self.density = parse_value(key, value)?,
+            "warm_start" => self.warm_start = parse_value(key, value)?,
+            "tolerance" => self.tolerance = parse_value(key, value)?,
+            "residual" => self.residual = parse_value(key, value)?,
+            "solver" => self.solver = parse_value(key, value)?,
+            "coarsening" => self.coarsening = parse_value(key, value)?,
+            "threads" => self.threads = parse_value(key, value)?,
+            "advection" => self.advection = parse_value(key, value)?,
+            "backtrace" => self.backtrace = parse_value(key, value)?,
+            "scene" => self.scene = Some(value.trim().to_string()),
+            "preset" => self.preset = Some(value.trim().to_string()),
+            _ => {
+                return Err(FluidError::InvalidConfig {
+                    key: key.to_string(),
+                    reason: format!("unknown setting, expected one of: {}", KEYS.join(", ")),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// checks every field is in a range the solver can actually run with
+    pub fn validate(&self) -> Result<(), FluidError> {
+        let invalid = |key: &str, reason: String| Err(FluidError::InvalidConfig { key: key.to_string(), reason });
+
+        // the solver reads one cell in from every edge, so anything smaller has no interior at all
+        if self.x < 3 || self.y < 3 {
+            let key = if self.x < 3 { "width" } else { "height" };
+            return invalid(key, format!("grid must be at least 3x3 cells, got {}x{}", self.x, self.y));
+        }
+        if !(self.overrelaxation > 0.0 && self.overrelaxation < 2.0) {
+            return invalid(
+                "overrelaxation",
+                format!("must be strictly between 0 and 2 for SOR to converge, got {}", self.overrelaxation),
+            );
+        }
+        if self.iters == 0 {
+            return invalid("iters", String::from("projection needs at least 1 iteration, got 0"));
+        }
+        for (key, value) in [
+            ("cell_size", self.cell_size),
+            ("delta_t", self.delta_t),
+            ("grid_size", self.grid_size),
+            ("visual_modifier", self.visual_modifier),
+            ("density", self.density),
+        ] {
+            if !(value.is_finite() && value > 0.0) {
+                return invalid(key, format!("must be a positive number, got {}", value));
+            }
+        }
+        if !self.source_velocity.is_finite() {
+            return invalid("source_velocity", format!("must be finite, got {}", self.source_velocity));
         }
+        if !(self.epsilon.is_finite() && self.epsilon >= 0.0) {
+            return invalid("epsilon", format!("must be zero or positive, got {}", self.epsilon));
+        }
+        if !(self.tolerance.is_finite() && self.tolerance >= 0.0) {
+            return invalid("tolerance", format!("must be zero or positive, got {}", self.tolerance));
+        }
+        if !(self.cfl.is_finite() && self.cfl >= 0.0) {
+            return invalid("cfl", format!("must be zero or positive, got {}", self.cfl));
+        }
+        if self.scene.is_some() && self.preset.is_some() {
+            return invalid("preset", String::from("a scene file and a preset can't both be given"));
+        }
+        if let Some(name) = &self.preset {
+            presets::find(name)?;
+        }
+
+        Ok(())
     }
 }
 
-pub fn configuration() -> Conf {
+/// every setting accepted in a config file or as a <--flag>
+pub const KEYS: [&str; 22] = [
+    "width",
+    "height",
+    "overrelaxation",
+    "cell_size",
+    "iters",
+    "delta_t",
+    "cfl",
+    "source_velocity",
+    "visual_modifier",
+    "grid_size",
+    "epsilon",
+    "density",
+    "warm_start",
+    "tolerance",
+    "residual",
+    "solver",
+    "coarsening",
+    "threads",
+    "advection",
+    "backtrace",
+    "scene",
+    "preset",
+];
+
+pub(crate) fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, FluidError> {
+    value.trim().parse::<T>().map_err(|_| FluidError::InvalidConfig {
+        key: key.to_string(),
+        reason: format!("could not parse '{}'", value.trim()),
+    })
+}
+
+/// splits <key = value> text into (line number, key, value) triples. <path> is only used to label errors
+pub fn parse_pairs(path: &str, text: &str) -> Result<Vec<(usize, String, String)>, FluidError> {
+    let mut pairs = Vec::new();
+
+    for (idx, raw) in text.lines().enumerate() {
+        let line = raw.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                pairs.push((idx + 1, key.trim().to_string(), value.trim().to_string()));
+            }
+            _ => {
+                return Err(FluidError::Parse {
+                    path: path.to_string(),
+                    line: idx + 1,
+                    reason: format!("expected 'key = value', got '{}'", line),
+                })
+            }
+        }
+    }
+
+    Ok(pairs)
+}
+
+/// splits <--key value> / <--key=value> flags into (key, value) pairs
+pub fn parse_flags<I: IntoIterator<Item = String>>(args: I) -> Result<Vec<(String, String)>, FluidError> {
+    let mut flags = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(FluidError::InvalidConfig {
+                key: arg.clone(),
+                reason: String::from("expected a --flag"),
+            });
+        };
+
+        if let Some((key, value)) = flag.split_once('=') {
+            flags.push((key.to_string(), value.to_string()));
+            continue;
+        }
+        match args.next() {
+            Some(value) => flags.push((flag.to_string(), value)),
+            None => {
+                return Err(FluidError::InvalidConfig {
+                    key: flag.to_string(),
+                    reason: String::from("missing value"),
+                })
+            }
+        }
+    }
+
+    Ok(flags)
+}
+
+/// window settings derived from the loaded config so the window always fits the grid exactly
+#[cfg(feature = "gui")]
+pub fn configuration(config: &Config) -> Conf {
     Conf {
         window_title: String::from("Fluids flowy flowy"),
-        window_height: (HEIGHT as f32 * CELL_SIZE) as i32,
-        window_width: (WIDTH as f32 * CELL_SIZE) as i32,
+        window_height: (config.y as f32 * config.cell_size) as i32,
+        window_width: (config.x as f32 * config.cell_size) as i32,
         window_resizable: false,
         ..Default::default()
     }
@@ -60,6 +337,12 @@ pub enum State {
     Pause,
 }
 
+impl Default for State {
+    fn default() -> State {
+        State::new()
+    }
+}
+
 impl State {
     pub fn new() -> State {
         Self::Pause
@@ -74,28 +357,295 @@ impl State {
 }
 
 #[allow(dead_code)]
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VisualMode {
     Gradient,
     Vector,
     Other,
     Streamline,
+    Pressure,
     Blank,
 }
 
+impl Default for VisualMode {
+    fn default() -> VisualMode {
+        VisualMode::new()
+    }
+}
+
 #[allow(dead_code)]
 impl VisualMode {
     pub fn new() -> VisualMode {
         Self::Gradient
     }
 
+    /// lowercase name, as used for batch frame outputs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gradient => "gradient",
+            Self::Vector => "vector",
+            Self::Other => "other",
+            Self::Streamline => "streamline",
+            Self::Pressure => "pressure",
+            Self::Blank => "blank",
+        }
+    }
+
     pub fn rotate(&self) -> VisualMode {
         match self {
             Self::Gradient => Self::Vector,
             Self::Vector => Self::Other,
             Self::Other => Self::Streamline,
-            Self::Streamline => Self::Blank,
+            Self::Streamline => Self::Pressure,
+            Self::Pressure => Self::Blank,
             Self::Blank => Self::Gradient,
         }
     }
 }
+
+/// measure of the divergence left over after projection, taken over the Fluid cells the solver can
+/// actually correct
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResidualNorm {
+    /// largest absolute divergence of any one cell
+    Max,
+    /// root mean square divergence
+    Rms,
+}
+
+impl Default for ResidualNorm {
+    fn default() -> ResidualNorm {
+        ResidualNorm::new()
+    }
+}
+
+impl ResidualNorm {
+    pub fn new() -> ResidualNorm {
+        Self::Max
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Max => "max",
+            Self::Rms => "rms",
+        }
+    }
+}
+
+impl FromStr for ResidualNorm {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<ResidualNorm, ()> {
+        match text {
+            "max" => Ok(Self::Max),
+            "rms" => Ok(Self::Rms),
+            _ => Err(()),
+        }
+    }
+}
+
+/// which <PressureSolver> projection runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Solver {
+    /// in-place successive over-relaxation, see <overrelaxation>
+    GaussSeidel,
+    /// the same relaxation in checkerboard order, spread over <threads>
+    RedBlack,
+    /// conjugate gradient with a MIC(0) preconditioner
+    Pcg,
+    /// geometric multigrid V-cycles. give it a <tolerance> - without one it cycles until the residual
+    /// stops dropping, which is far past anything visible
+    Multigrid,
+}
+
+impl Default for Solver {
+    fn default() -> Solver {
+        Solver::new()
+    }
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Self::GaussSeidel
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GaussSeidel => "gauss_seidel",
+            Self::RedBlack => "red_black",
+            Self::Pcg => "pcg",
+            Self::Multigrid => "multigrid",
+        }
+    }
+}
+
+impl FromStr for Solver {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Solver, ()> {
+        match text {
+            "gauss_seidel" => Ok(Self::GaussSeidel),
+            "red_black" => Ok(Self::RedBlack),
+            "pcg" => Ok(Self::Pcg),
+            "multigrid" => Ok(Self::Multigrid),
+            _ => Err(()),
+        }
+    }
+}
+
+/// how velocities are carried along with the flow each step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Advection {
+    /// first order semi-lagrangian. cheapest and stable, but bilinear sampling smears vortices out
+    SemiLagrangian,
+    /// a semi-lagrangian step corrected by half the error of tracing it back again. one extra step
+    MacCormack,
+    /// back and forth error compensation - the same error estimate applied before a final step
+    /// instead of after, for two extra steps
+    Bfecc,
+}
+
+impl Default for Advection {
+    fn default() -> Advection {
+        Advection::new()
+    }
+}
+
+impl Advection {
+    pub fn new() -> Advection {
+        Self::SemiLagrangian
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SemiLagrangian => "semi_lagrangian",
+            Self::MacCormack => "maccormack",
+            Self::Bfecc => "bfecc",
+        }
+    }
+}
+
+impl FromStr for Advection {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Advection, ()> {
+        match text {
+            "semi_lagrangian" => Ok(Self::SemiLagrangian),
+            "maccormack" => Ok(Self::MacCormack),
+            "bfecc" => Ok(Self::Bfecc),
+            _ => Err(()),
+        }
+    }
+}
+
+/// how far back along the flow each face's departure point is found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backtrace {
+    /// a single straight step along the face's own velocity
+    Euler,
+    /// midpoint method, one extra velocity sample per face
+    Rk2,
+    /// ralston's third order method, two extra samples
+    Rk3,
+    /// classic fourth order runge-kutta, three extra samples
+    Rk4,
+}
+
+impl Default for Backtrace {
+    fn default() -> Backtrace {
+        Backtrace::new()
+    }
+}
+
+impl Backtrace {
+    pub fn new() -> Backtrace {
+        Self::Euler
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Euler => "euler",
+            Self::Rk2 => "rk2",
+            Self::Rk3 => "rk3",
+            Self::Rk4 => "rk4",
+        }
+    }
+}
+
+impl FromStr for Backtrace {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Backtrace, ()> {
+        match text {
+            "euler" => Ok(Self::Euler),
+            "rk2" => Ok(Self::Rk2),
+            "rk3" => Ok(Self::Rk3),
+            "rk4" => Ok(Self::Rk4),
+            _ => Err(()),
+        }
+    }
+}
+
+/// how each 2x2 block of cells is merged into one cell of the next level down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coarsening {
+    /// solid only when the whole block is. every passage stays open on coarse levels, though walls
+    /// thinner than the block disappear from them
+    Conservative,
+    /// solid when most of the block is, so walls keep their shape on coarse levels but passages
+    /// narrower than the block close up
+    Majority,
+}
+
+impl Default for Coarsening {
+    fn default() -> Coarsening {
+        Coarsening::new()
+    }
+}
+
+impl Coarsening {
+    pub fn new() -> Coarsening {
+        Self::Conservative
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Conservative => "conservative",
+            Self::Majority => "majority",
+        }
+    }
+}
+
+impl FromStr for Coarsening {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Coarsening, ()> {
+        match text {
+            "conservative" => Ok(Self::Conservative),
+            "majority" => Ok(Self::Majority),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fluid::{Ele, Fluid};
+    use crate::testing::{sized, stepped_fluid};
+
+    #[test]
+    fn default_layout_fits_any_valid_grid() {
+        for (x, y) in [(3, 3), (100, 100), (60, 240)] {
+            let config: Config = sized(x, y);
+            config.validate().unwrap();
+
+            let fluid: Fluid = stepped_fluid(&config, 1);
+            assert!(fluid.is_finite());
+            if y >= 100 {
+                let inlet = (0..y).filter(|&row| matches!(fluid.element[row][0], Ele::Source(_))).count();
+                assert!(inlet > 0, "{}x{} has no inflow", x, y);
+            }
+        }
+    }
+}
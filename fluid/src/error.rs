@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// recoverable errors raised by the solver api. anything that used to end the process now surfaces
+/// as one of these so an embedding host can decide what to do with it
+#[derive(Debug, Clone, PartialEq)]
+pub enum FluidError {
+    /// neighbour lookups only accept the four axis offsets (±1, 0) and (0, ±1)
+    InvalidOffset { dx: isize, dy: isize },
+
+    /// a cell coordinate that falls outside the grid. signed so negative coordinates are reported as-is
+    /// rather than wrapped
+    OutOfBounds { x: isize, y: isize },
+
+    /// a setting that could not be parsed or is outside the range the solver can run with
+    InvalidConfig { key: String, reason: String },
+
+    /// malformed line in a text input file
+    Parse { path: String, line: usize, reason: String },
+
+    /// a file could not be read or written
+    Io { path: String, reason: String },
+
+    /// a checkpoint file that was read but can't be restored - wrong version, truncated or corrupt
+    Checkpoint { path: String, reason: String },
+
+    /// velocities went to inf or nan during a step
+    Diverged { step: usize, time: f64 },
+}
+
+impl fmt::Display for FluidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidOffset { dx, dy } => {
+                write!(f, "neighbour offset ({}, {}) is not an axis direction", dx, dy)
+            }
+            Self::OutOfBounds { x, y } => write!(f, "cell ({}, {}) is outside the grid", x, y),
+            Self::InvalidConfig { key, reason } => write!(f, "invalid setting '{}': {}", key, reason),
+            Self::Parse { path, line, reason } => write!(f, "{}:{}: {}", path, line, reason),
+            Self::Io { path, reason } => write!(f, "{}: {}", path, reason),
+            Self::Checkpoint { path, reason } => write!(f, "{}: bad checkpoint: {}", path, reason),
+            Self::Diverged { step, time } => {
+                write!(f, "simulation diverged at step {} (t = {:.3}s), velocities are no longer finite", step, time)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FluidError {}
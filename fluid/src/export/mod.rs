@@ -0,0 +1,112 @@
+//! field dumps for post-processing outside the solver
+//!
+//! every format is written from the same <CellFields> snapshot, so the numbers agree whichever tool
+//! reads them. quantities are cell-centred: the staggered faces are averaged onto cell centres and
+//! derivatives are taken over <grid_size>, the physical width of a cell
+
+pub mod npy;
+pub mod vtk;
+
+use crate::fluid;
+
+use fluid::{Ele, Fluid};
+
+/// integer code for each kind of cell, as written to every export
+pub fn cell_code(ele: &Ele) -> i32 {
+    match ele {
+        Ele::Fluid => 0,
+        Ele::Static => 1,
+        Ele::Source(_) => 2,
+        Ele::Clone(_) => 3,
+    }
+}
+
+/// cell-centred copy of the fluid state, row-major with row 0 at the top of the grid like the solver
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellFields {
+    pub width: usize,
+    pub height: usize,
+    pub spacing: f32,
+
+    pub u: Vec<f32>,
+    pub v: Vec<f32>,
+    /// dv/dx - du/dy with y pointing down, so clockwise rotation on screen is positive
+    pub vorticity: Vec<f32>,
+    /// what the last projection recovered, see <Fluid::pressure>
+    pub pressure: Vec<f32>,
+    pub divergence: Vec<f32>,
+    pub cell_type: Vec<i32>,
+}
+
+impl CellFields {
+    pub fn from_fluid(fluid: &Fluid) -> CellFields {
+        let (width, height) = (fluid.x, fluid.y);
+        let spacing = fluid.grid_size;
+        let cells = width * height;
+
+        let mut u: Vec<f32> = Vec::with_capacity(cells);
+        let mut v: Vec<f32> = Vec::with_capacity(cells);
+        let mut divergence: Vec<f32> = Vec::with_capacity(cells);
+        let mut cell_type: Vec<i32> = Vec::with_capacity(cells);
+        for y in 0..height {
+            for x in 0..width {
+                let (left, right) = fluid.u.cell_faces(x, y);
+                let (top, bottom) = fluid.v.cell_faces(x, y);
+                u.push((left + right) * 0.5);
+                v.push((top + bottom) * 0.5);
+                divergence.push(fluid.divergence_at(x, y) / spacing);
+                cell_type.push(cell_code(&fluid.element[y][x]));
+            }
+        }
+
+        // central differences inside the grid, one-sided on the edges
+        let derivative = |field: &[f32], x: usize, y: usize, along_x: bool| -> f32 {
+            let (lo, hi) = if along_x {
+                (y * width + x.saturating_sub(1), y * width + (x + 1).min(width - 1))
+            }
+            else {
+                (y.saturating_sub(1) * width + x, (y + 1).min(height - 1) * width + x)
+            };
+            let steps = if along_x { hi - lo } else { (hi - lo) / width };
+            if steps == 0 {
+                0.0
+            }
+            else {
+                (field[hi] - field[lo]) / (steps as f32 * spacing)
+            }
+        };
+
+        let mut vorticity: Vec<f32> = Vec::with_capacity(cells);
+        for y in 0..height {
+            for x in 0..width {
+                vorticity.push(derivative(&v, x, y, true) - derivative(&u, x, y, false));
+            }
+        }
+
+        CellFields {
+            width,
+            height,
+            spacing,
+            u,
+            v,
+            vorticity,
+            pressure: fluid.pressure.as_slice().to_vec(),
+            divergence,
+            cell_type,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sized, stepped_fluid};
+
+    #[test]
+    fn exported_pressure_is_the_recovered_field() {
+        let fluid: Fluid = stepped_fluid(&sized(30, 20), 3);
+        let fields = CellFields::from_fluid(&fluid);
+        assert_eq!(fields.pressure, fluid.pressure.as_slice());
+        assert!(fields.pressure.iter().any(|pressure| *pressure != 0.0));
+    }
+}
@@ -0,0 +1,450 @@
+//! numpy <.npy> arrays and <.npz> bundles
+//!
+//! the velocity grids are written as stored, staggered - <u> is height x (width + 1) and <v> is
+//! (height + 1) x width - so nothing is lost on the way out and back in. <vorticity> and <pressure>
+//! are cell-centred height x width float32 arrays and <element> is int32 with 0 fluid, 1 static,
+//! 2 source and 3 clone. row 0 is the top of the grid, as in the window
+//!
+//! ```python
+//! data = numpy.load("npz/frame_000100.npz")
+//! speed = numpy.hypot(data["u"][:, 1:] + data["u"][:, :-1], data["v"][1:] + data["v"][:-1]) / 2
+//! ```
+//!
+//! going the other way, <load_fluid> seeds a fluid from an element mask and u / v arrays, which may be
+//! staggered as above or cell-centred (height x width)
+
+use crate::clone;
+use crate::config;
+use crate::error;
+use crate::field;
+use crate::fluid;
+use crate::fluidapi;
+use crate::scene;
+use crate::source;
+use crate::utils;
+
+use std::{fs, path::Path};
+
+use super::CellFields;
+use clone::Clone;
+use config::Config;
+use error::FluidError;
+use field::Field2D;
+use fluid::{Ele, Fluid};
+use fluidapi::Oo;
+use scene::{Directive, Scene};
+use source::Source;
+use utils::crc32;
+
+const MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+/// element of a 2d array. everything is converted to one of these two on load
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayData {
+    F32(Vec<f32>),
+    I32(Vec<i32>),
+}
+
+/// row-major 2d array, <shape> being (rows, columns) as numpy reports it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Array {
+    pub shape: (usize, usize),
+    pub data: ArrayData,
+}
+
+impl Array {
+    pub fn from_field(field: &Field2D<f32>) -> Array {
+        Array { shape: (field.height, field.width), data: ArrayData::F32(field.as_slice().to_vec()) }
+    }
+
+    pub fn from_file(path: &str) -> Result<Array, FluidError> {
+        let bytes: Vec<u8> = fs::read(path).map_err(|err| io_error(path, err.to_string()))?;
+        Array::decode(&bytes).map_err(|reason| io_error(path, reason))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), FluidError> {
+        fs::write(path, self.encode()).map_err(|err| io_error(&path.display().to_string(), err.to_string()))
+    }
+
+    /// version 1.0 npy bytes, little endian
+    pub fn encode(&self) -> Vec<u8> {
+        let descr = match self.data {
+            ArrayData::F32(_) => "<f4",
+            ArrayData::I32(_) => "<i4",
+        };
+        let (rows, columns) = self.shape;
+        let mut header =
+            format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}", descr, rows, columns);
+        // magic, version and length take 10 bytes and the whole preamble is padded to 64 with a newline
+        let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+
+        let mut bytes: Vec<u8> = Vec::with_capacity(10 + header.len() + self.len() * 4);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        match &self.data {
+            ArrayData::F32(values) => values.iter().for_each(|value| bytes.extend_from_slice(&value.to_le_bytes())),
+            ArrayData::I32(values) => values.iter().for_each(|value| bytes.extend_from_slice(&value.to_le_bytes())),
+        }
+        bytes
+    }
+
+    /// reads 2d npy arrays of any float, signed, unsigned or bool dtype, either byte order, c or
+    /// fortran order. floats come back as F32 and everything else as I32
+    pub fn decode(bytes: &[u8]) -> Result<Array, String> {
+        if !bytes.starts_with(MAGIC) || bytes.len() < 10 {
+            return Err(String::from("not a numpy .npy file"));
+        }
+        let (header_len, start) = match bytes[6] {
+            1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+            2 | 3 if bytes.len() >= 12 => {
+                (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12)
+            }
+            version => return Err(format!("unsupported .npy version {}", version)),
+        };
+        let header = bytes
+            .get(start..start + header_len)
+            .and_then(|header| std::str::from_utf8(header).ok())
+            .ok_or("truncated .npy header")?;
+        let data = &bytes[start + header_len..];
+
+        let descr = header_value(header, "descr").ok_or("header has no dtype")?;
+        let descr = descr.trim_matches(|c| c == '\'' || c == '"');
+        let fortran = header_value(header, "fortran_order").ok_or("header has no fortran_order")? == "True";
+        let shape: Vec<usize> = header_value(header, "shape")
+            .ok_or("header has no shape")?
+            .trim_matches(|c| c == '(' || c == ')')
+            .split(',')
+            .filter(|dim| !dim.trim().is_empty())
+            .map(|dim| dim.trim().parse().map_err(|_| format!("bad shape entry '{}'", dim.trim())))
+            .collect::<Result<_, _>>()?;
+        let [rows, columns] = shape[..]
+        else {
+            return Err(format!("expected a 2d array, got {} dimensions", shape.len()));
+        };
+
+        let (order, kind, size) = match descr.as_bytes() {
+            [order @ (b'<' | b'>' | b'|' | b'='), kind, size @ ..] => {
+                let size: usize = std::str::from_utf8(size).ok().and_then(|size| size.parse().ok()).unwrap_or(0);
+                (*order, *kind, size)
+            }
+            _ => return Err(format!("unsupported dtype '{}'", descr)),
+        };
+        if size == 0 || size > 8 {
+            return Err(format!("unsupported dtype '{}'", descr));
+        }
+        // the shape comes straight from the file, so a corrupt one mustn't be allowed to wrap around
+        let too_large = || format!("a {}x{} array is too large to load", rows, columns);
+        let count: usize = rows.checked_mul(columns).ok_or_else(too_large)?;
+        if data.len() < count.checked_mul(size).ok_or_else(too_large)? {
+            return Err(format!("array data is shorter than its {}x{} shape", rows, columns));
+        }
+
+        let mut values: Vec<f64> = Vec::with_capacity(count);
+        for chunk in data.chunks_exact(size).take(count) {
+            let mut raw = [0u8; 8];
+            raw[..size].copy_from_slice(chunk);
+            if order == b'>' {
+                raw[..size].reverse();
+            }
+            let value: f64 = match (kind, size) {
+                (b'f', 4) => f32::from_le_bytes(raw[..4].try_into().unwrap()) as f64,
+                (b'f', 8) => f64::from_le_bytes(raw),
+                (b'i', 1) => raw[0] as i8 as f64,
+                (b'i', 2) => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+                (b'i', 4) => i32::from_le_bytes(raw[..4].try_into().unwrap()) as f64,
+                (b'i', 8) => i64::from_le_bytes(raw) as f64,
+                (b'u' | b'b', _) => u64::from_le_bytes(raw) as f64,
+                _ => return Err(format!("unsupported dtype '{}'", descr)),
+            };
+            values.push(value);
+        }
+
+        // fortran order stores columns first
+        if fortran {
+            values = (0..count).map(|idx| values[(idx % columns) * rows + idx / columns]).collect();
+        }
+
+        let data = if kind == b'f' {
+            ArrayData::F32(values.iter().map(|value| *value as f32).collect())
+        }
+        else {
+            ArrayData::I32(values.iter().map(|value| *value as i32).collect())
+        };
+        Ok(Array { shape: (rows, columns), data })
+    }
+
+    pub fn len(&self) -> usize {
+        self.shape.0 * self.shape.1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_f32(&self) -> Vec<f32> {
+        match &self.data {
+            ArrayData::F32(values) => values.clone(),
+            ArrayData::I32(values) => values.iter().map(|value| *value as f32).collect(),
+        }
+    }
+}
+
+/// raw text of a key's value in a numpy header dict
+fn header_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') { rest.find(')')? + 1 } else { rest.find([',', '}'])? };
+    Some(rest[..end].trim())
+}
+
+fn io_error(path: &str, reason: String) -> FluidError {
+    FluidError::Io { path: path.to_string(), reason }
+}
+
+/// the exported arrays, named as they are in files
+pub fn arrays(fluid: &Fluid) -> Vec<(&'static str, Array)> {
+    let fields = CellFields::from_fluid(fluid);
+    let shape = (fluid.y, fluid.x);
+    vec![
+        ("u", Array::from_field(&fluid.u)),
+        ("v", Array::from_field(&fluid.v)),
+        ("vorticity", Array { shape, data: ArrayData::F32(fields.vorticity) }),
+        ("pressure", Array { shape, data: ArrayData::F32(fields.pressure) }),
+        ("element", Array { shape, data: ArrayData::I32(fields.cell_type) }),
+    ]
+}
+
+/// writes every array as <DIR/STEM_NAME.npy>
+pub fn write_npy(fluid: &Fluid, dir: &Path, stem: &str) -> Result<(), FluidError> {
+    for (name, array) in arrays(fluid) {
+        array.write(&dir.join(format!("{}_{}.npy", stem, name)))?;
+    }
+    Ok(())
+}
+
+/// writes every array into one uncompressed zip, which is all <numpy.savez> produces
+pub fn write_npz(fluid: &Fluid, path: &Path) -> Result<(), FluidError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut directory: Vec<u8> = Vec::new();
+    let entries = arrays(fluid);
+
+    for (name, array) in &entries {
+        let name = format!("{}.npy", name);
+        let data = array.encode();
+        let offset = bytes.len() as u32;
+        let crc = crc32(&data);
+
+        // local header, then the matching central directory record. no compression, dated 1980-01-01
+        bytes.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        bytes.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);
+        for value in [crc, data.len() as u32, data.len() as u32] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&data);
+
+        directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        directory.extend_from_slice(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0x21, 0]);
+        for value in [crc, data.len() as u32, data.len() as u32] {
+            directory.extend_from_slice(&value.to_le_bytes());
+        }
+        directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        directory.extend_from_slice(&[0; 12]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = bytes.len() as u32;
+    bytes.extend_from_slice(&directory);
+    bytes.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&directory_offset.to_le_bytes());
+    bytes.extend_from_slice(&[0, 0]);
+
+    fs::write(path, bytes).map_err(|err| io_error(&path.display().to_string(), err.to_string()))
+}
+
+/// builds a fluid from an element mask (the grid size comes from its shape) and u / v velocity arrays.
+/// solver settings come from <config>. sources blow at the configured source velocity and clone cells
+/// must sit on the edge of the grid, copying the cell one step in
+pub fn load_fluid(config: &Config, u_path: &str, v_path: &str, element_path: &str) -> Result<Fluid, FluidError> {
+    let element = Array::from_file(element_path)?;
+    let (height, width) = element.shape;
+    if width < 3 || height < 3 {
+        return Err(io_error(element_path, format!("a {}x{} mask is too small to simulate", width, height)));
+    }
+    let ArrayData::I32(codes) = &element.data
+    else {
+        return Err(io_error(element_path, String::from("element mask must be an integer array")));
+    };
+
+    let mut config = config.clone();
+    config.x = width;
+    config.y = height;
+    let mut fluid: Fluid = Fluid::construct(&config);
+
+    for y in 0..height {
+        for x in 0..width {
+            let ele = match codes[y * width + x] {
+                0 => continue,
+                1 => Ele::Static,
+                2 => Ele::Source(Source::construct(fluid.source_velocity, 0.0)),
+                3 => {
+                    let offset = match (x, y) {
+                        (0, _) => (1, 0),
+                        (_, 0) => (0, 1),
+                        _ if x == width - 1 => (-1, 0),
+                        _ if y == height - 1 => (0, -1),
+                        _ => {
+                            let reason = format!("clone cell ({}, {}) isn't on the edge of the grid", x, y);
+                            return Err(io_error(element_path, reason));
+                        }
+                    };
+                    Ele::Clone(Clone::construct(offset.0, offset.1))
+                }
+                code => return Err(io_error(element_path, format!("unknown element code {} at ({}, {})", code, x, y))),
+            };
+            Oo::construct(x, y, &mut fluid).set_here(ele);
+        }
+    }
+
+    read_velocity(&mut fluid.u, u_path, true)?;
+    read_velocity(&mut fluid.v, v_path, false)?;
+
+    // resets keep the loaded geometry rather than falling back to the built-in layout
+    let (mask, rules) = fluid.geometry_mask();
+    fluid.scene = Some(Scene { directives: vec![Directive::Mask { mask, rules }] });
+
+    Ok(fluid)
+}
+
+/// fills a face grid from a staggered array of the same shape, or averages a cell-centred one onto the
+/// faces, with edge faces taking the value of the only cell they touch
+fn read_velocity(faces: &mut Field2D<f32>, path: &str, horizontal: bool) -> Result<(), FluidError> {
+    let array = Array::from_file(path)?;
+    let values = array.to_f32();
+    let (cells_y, cells_x) = if horizontal { (faces.height, faces.width - 1) } else { (faces.height - 1, faces.width) };
+
+    if array.shape == (faces.height, faces.width) {
+        faces.as_mut_slice().copy_from_slice(&values);
+    }
+    else if array.shape == (cells_y, cells_x) {
+        for y in 0..faces.height {
+            for x in 0..faces.width {
+                let cell = |x: usize, y: usize| values[y.min(cells_y - 1) * cells_x + x.min(cells_x - 1)];
+                faces[y][x] = if horizontal {
+                    (cell(x.saturating_sub(1), y) + cell(x, y)) * 0.5
+                }
+                else {
+                    (cell(x, y.saturating_sub(1)) + cell(x, y)) * 0.5
+                };
+            }
+        }
+    }
+    else {
+        return Err(io_error(
+            path,
+            format!(
+                "shape ({}, {}) matches neither the faces ({}, {}) nor the cells ({}, {}) of the grid",
+                array.shape.0, array.shape.1, faces.height, faces.width, cells_y, cells_x
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{scratch_dir, sized, stepped_fluid};
+
+    /// a version 1.0 file with a hand-written header dict
+    fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = MAGIC.to_vec();
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn shapes_that_overflow_are_refused() {
+        let huge = usize::MAX / 2 + 1;
+        for shape in [format!("({}, {})", huge, 4), format!("({}, 1)", huge)] {
+            let header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}\n", shape);
+            assert!(Array::decode(&npy(&header, &[0; 64])).is_err());
+        }
+    }
+
+    #[test]
+    fn arrays_survive_encoding() {
+        let floats = Array { shape: (2, 3), data: ArrayData::F32(vec![0.5, -1.0, 2.25, 1e-8, f32::MAX, 0.0]) };
+        let ints = Array { shape: (3, 2), data: ArrayData::I32(vec![0, 1, 2, 3, -4, i32::MAX]) };
+        for array in [floats, ints] {
+            assert_eq!(Array::decode(&array.encode()).unwrap(), array);
+        }
+    }
+
+    #[test]
+    fn fortran_order_and_big_endian_are_read() {
+        let header = "{'descr': '>i2', 'fortran_order': True, 'shape': (2, 3), }\n";
+        let data: Vec<u8> = [1i16, 4, 2, 5, 3, 6].iter().flat_map(|value| value.to_be_bytes()).collect();
+        let array = Array::decode(&npy(header, &data)).unwrap();
+        assert_eq!(array.data, ArrayData::I32(vec![1, 2, 3, 4, 5, 6]));
+    }
+
+    #[test]
+    fn npy_files_load_back_into_the_same_fluid() {
+        let fluid: Fluid = stepped_fluid(&sized(30, 20), 3);
+        let dir = scratch_dir("npy");
+        write_npy(&fluid, &dir, "frame").unwrap();
+
+        let path = |name: &str| dir.join(format!("frame_{}.npy", name)).display().to_string();
+        let config: Config = Config::new();
+        let loaded: Fluid = load_fluid(&config, &path("u"), &path("v"), &path("element")).unwrap();
+        assert_eq!(loaded.u, fluid.u);
+        assert_eq!(loaded.v, fluid.v);
+        assert_eq!(loaded.element, fluid.element);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn npz_bundles_hold_every_array() {
+        let fluid: Fluid = stepped_fluid(&sized(30, 20), 3);
+        let dir = scratch_dir("npz");
+        let path = dir.join("frame.npz");
+        write_npz(&fluid, &path).unwrap();
+        let bytes: Vec<u8> = fs::read(&path).unwrap();
+
+        // walk the stored entries through their local headers
+        let mut found: Vec<(String, Array)> = Vec::new();
+        let mut pos: usize = 0;
+        while bytes[pos..].starts_with(&0x04034b50u32.to_le_bytes()) {
+            let word = |at: usize| u32::from_le_bytes(bytes[pos + at..pos + at + 4].try_into().unwrap()) as usize;
+            let (crc, size) = (word(14) as u32, word(18));
+            let name_len = u16::from_le_bytes([bytes[pos + 26], bytes[pos + 27]]) as usize;
+            let name = String::from_utf8(bytes[pos + 30..pos + 30 + name_len].to_vec()).unwrap();
+            let data = &bytes[pos + 30 + name_len..pos + 30 + name_len + size];
+            assert_eq!(crc32(data), crc);
+            found.push((name, Array::decode(data).unwrap()));
+            pos += 30 + name_len + size;
+        }
+
+        let expected: Vec<(String, Array)> =
+            arrays(&fluid).into_iter().map(|(name, array)| (format!("{}.npy", name), array)).collect();
+        assert_eq!(found, expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! vtk files for paraview
+//!
+//! <.vti> is the xml image data format and legacy <.vtk> the older structured points one - both
+//! hold the same cell data. paraview puts y up, so rows are written bottom first and the y velocity
+//! and vorticity are flipped to match, leaving the picture the way the window shows it. a <.pvd>
+//! collection ties a run's files to their simulated times so they load as one animation
+
+use crate::error;
+use crate::fluid;
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::CellFields;
+use error::FluidError;
+use fluid::Fluid;
+
+/// rows in bottom-first order
+fn flipped<T: Copy>(fields: &CellFields, values: &[T]) -> Vec<T> {
+    values.chunks(fields.width).rev().flatten().copied().collect()
+}
+
+/// scalar arrays written alongside the velocity vectors, already in y-up order
+fn scalars(fields: &CellFields) -> [(&'static str, Vec<f32>); 3] {
+    let vorticity: Vec<f32> = flipped(fields, &fields.vorticity).iter().map(|value| 0.0 - value).collect();
+    [
+        ("vorticity", vorticity),
+        ("pressure", flipped(fields, &fields.pressure)),
+        ("divergence", flipped(fields, &fields.divergence)),
+    ]
+}
+
+/// velocity as xyz triples in y-up order, z always 0 so paraview's glyph filter takes it as is
+fn velocity(fields: &CellFields) -> Vec<f32> {
+    let (u, v) = (flipped(fields, &fields.u), flipped(fields, &fields.v));
+    u.iter().zip(v).flat_map(|(u, v)| [*u, 0.0 - v, 0.0]).collect()
+}
+
+/// space separated values, wrapped so files stay friendly to line-based tools
+fn join<T: std::fmt::Display>(values: &[T], per_line: usize) -> String {
+    let mut text = String::new();
+    for line in values.chunks(per_line) {
+        for (idx, value) in line.iter().enumerate() {
+            if idx > 0 {
+                text.push(' ');
+            }
+            let _ = write!(text, "{}", value);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// <value> made safe to put between the double quotes of an xml attribute
+fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// undoes <escape_attribute()>. &amp; goes last so an escaped entity like &amp;lt; stays literal
+fn unescape_attribute(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn write_file(path: &Path, text: String) -> Result<(), FluidError> {
+    fs::write(path, text).map_err(|err| FluidError::Io { path: path.display().to_string(), reason: err.to_string() })
+}
+
+pub fn write_vti(fluid: &Fluid, path: &Path) -> Result<(), FluidError> {
+    let fields = CellFields::from_fluid(fluid);
+    let (width, height) = (fields.width, fields.height);
+
+    let mut text = String::new();
+    text.push_str("<?xml version=\"1.0\"?>\n");
+    text.push_str("<VTKFile type=\"ImageData\" version=\"1.0\" byte_order=\"LittleEndian\">\n");
+    let _ = writeln!(
+        text,
+        "  <ImageData WholeExtent=\"0 {} 0 {} 0 0\" Origin=\"0 0 0\" Spacing=\"{} {} 1\">",
+        width, height, fields.spacing, fields.spacing
+    );
+    let _ = writeln!(text, "    <Piece Extent=\"0 {} 0 {} 0 0\">", width, height);
+    text.push_str("      <CellData Vectors=\"velocity\" Scalars=\"pressure\">\n");
+
+    text.push_str("        <DataArray type=\"Float32\" Name=\"velocity\" NumberOfComponents=\"3\" format=\"ascii\">\n");
+    text.push_str(&join(&velocity(&fields), 3 * width.min(64)));
+    text.push_str("        </DataArray>\n");
+    for (name, values) in scalars(&fields) {
+        let _ = writeln!(text, "        <DataArray type=\"Float32\" Name=\"{}\" format=\"ascii\">", name);
+        text.push_str(&join(&values, width.min(64)));
+        text.push_str("        </DataArray>\n");
+    }
+    text.push_str("        <DataArray type=\"Int32\" Name=\"cell_type\" format=\"ascii\">\n");
+    text.push_str(&join(&flipped(&fields, &fields.cell_type), width.min(64)));
+    text.push_str("        </DataArray>\n");
+
+    text.push_str("      </CellData>\n    </Piece>\n  </ImageData>\n</VTKFile>\n");
+    write_file(path, text)
+}
+
+pub fn write_legacy(fluid: &Fluid, path: &Path) -> Result<(), FluidError> {
+    let fields = CellFields::from_fluid(fluid);
+    let (width, height) = (fields.width, fields.height);
+
+    let mut text = String::new();
+    text.push_str("# vtk DataFile Version 3.0\n");
+    let _ = writeln!(text, "fluid step {} time {}", fluid.step, fluid.time);
+    text.push_str("ASCII\nDATASET STRUCTURED_POINTS\n");
+    let _ = writeln!(text, "DIMENSIONS {} {} 1", width + 1, height + 1);
+    text.push_str("ORIGIN 0 0 0\n");
+    let _ = writeln!(text, "SPACING {} {} 1", fields.spacing, fields.spacing);
+    let _ = writeln!(text, "CELL_DATA {}", width * height);
+
+    text.push_str("VECTORS velocity float\n");
+    text.push_str(&join(&velocity(&fields), 3 * width.min(64)));
+    for (name, values) in scalars(&fields) {
+        let _ = writeln!(text, "SCALARS {} float 1\nLOOKUP_TABLE default", name);
+        text.push_str(&join(&values, width.min(64)));
+    }
+    text.push_str("SCALARS cell_type int 1\nLOOKUP_TABLE default\n");
+    text.push_str(&join(&flipped(&fields, &fields.cell_type), width.min(64)));
+
+    write_file(path, text)
+}
+
+/// collection file listing (simulated time, data file) pairs. data file paths are written relative
+/// to the collection's own directory when they're inside it, which is how paraview resolves them
+pub fn write_pvd(path: &Path, entries: &[(f64, PathBuf)]) -> Result<(), FluidError> {
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let mut text = String::new();
+    text.push_str("<?xml version=\"1.0\"?>\n");
+    text.push_str("<VTKFile type=\"Collection\" version=\"1.0\" byte_order=\"LittleEndian\">\n  <Collection>\n");
+    for (time, file) in entries {
+        let file = escape_attribute(&file.strip_prefix(base).unwrap_or(file).to_string_lossy());
+        let _ = writeln!(text, "    <DataSet timestep=\"{}\" part=\"0\" file=\"{}\"/>", time, file);
+    }
+    text.push_str("  </Collection>\n</VTKFile>\n");
+    write_file(path, text)
+}
+
+/// reads back the (simulated time, data file) pairs of a collection written by <write_pvd()>, with
+/// relative paths resolved against its directory. a missing file is an empty collection
+pub fn read_pvd(path: &Path) -> Result<Vec<(f64, PathBuf)>, FluidError> {
+    let text: String = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(FluidError::Io { path: path.display().to_string(), reason: err.to_string() }),
+    };
+    let base = path.parent().unwrap_or(Path::new(""));
+    let attribute = |line: &str, name: &str| -> Option<String> {
+        let start = line.find(&format!("{}=\"", name))? + name.len() + 2;
+        let length = line[start..].find('"')?;
+        Some(unescape_attribute(&line[start..start + length]))
+    };
+
+    let mut entries: Vec<(f64, PathBuf)> = Vec::new();
+    for (idx, line) in text.lines().enumerate().filter(|(_, line)| line.trim_start().starts_with("<DataSet")) {
+        let time = attribute(line, "timestep").and_then(|time| time.parse::<f64>().ok());
+        let (Some(time), Some(file)) = (time, attribute(line, "file"))
+        else {
+            return Err(FluidError::Parse {
+                path: path.display().to_string(),
+                line: idx + 1,
+                reason: String::from("data set without a timestep and file"),
+            });
+        };
+        entries.push((time, base.join(file)));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch_dir;
+
+    #[test]
+    fn collections_escape_their_file_names() {
+        let dir = scratch_dir("pvd & <\"quoted\">");
+        let outside = PathBuf::from("/elsewhere/a&b<c>'d\".vti");
+        let entries = vec![(0.5, dir.join("vti").join("frame_000001.vti")), (1.0, outside)];
+        let path = dir.join("fluid.pvd");
+        write_pvd(&path, &entries).unwrap();
+
+        let text: String = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("file=\"/elsewhere/a&amp;b&lt;c&gt;&apos;d&quot;.vti\""), "{}", text);
+        assert_eq!(read_pvd(&path).unwrap(), entries);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use std::ops::{Index, IndexMut};
+
+/// where the samples of a field sit relative to the cell they are indexed by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stagger {
+    /// one sample per cell, taken at the cell centre
+    Centred,
+
+    /// horizontal velocity faces. sample (x, y) sits on the left face of cell (x, y), so there is one
+    /// extra column to close off the right edge of the grid
+    FaceU,
+
+    /// vertical velocity faces. sample (x, y) sits on the top face of cell (x, y), so there is one
+    /// extra row to close off the bottom edge of the grid
+    FaceV,
+}
+
+impl Stagger {
+    /// sample offset from the cell's top-left corner, in cells
+    pub fn offset(&self) -> (f32, f32) {
+        match self {
+            Self::Centred => (0.5, 0.5),
+            Self::FaceU => (0.0, 0.5),
+            Self::FaceV => (0.5, 0.0),
+        }
+    }
+}
+
+/// contiguous row-major 2d grid
+///
+/// indexing with <field[y][x]> returns a row slice so existing grid code reads the same as it did with
+/// nested vectors, but the whole grid sits in one allocation and a lookup is a single offset
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field2D<T> {
+    pub width: usize,
+    pub height: usize,
+    pub stagger: Stagger,
+    data: Vec<T>,
+}
+
+impl<T: Copy> Field2D<T> {
+    /// cell-centred field covering an x by y cell grid
+    pub fn construct(x: usize, y: usize, fill: T) -> Field2D<T> {
+        Field2D { width: x, height: y, stagger: Stagger::Centred, data: vec![fill; x * y] }
+    }
+
+    /// horizontal face field for an x by y cell grid: +1 in the x-direction
+    pub fn faces_u(x: usize, y: usize, fill: T) -> Field2D<T> {
+        Field2D { width: x + 1, height: y, stagger: Stagger::FaceU, data: vec![fill; (x + 1) * y] }
+    }
+
+    /// vertical face field for an x by y cell grid: +1 in the y-direction
+    pub fn faces_v(x: usize, y: usize, fill: T) -> Field2D<T> {
+        Field2D { width: x, height: y + 1, stagger: Stagger::FaceV, data: vec![fill; x * (y + 1)] }
+    }
+
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self.data[y * self.width + x]
+    }
+
+    #[inline]
+    pub fn set(&mut self, x: usize, y: usize, value: T) {
+        self.data[y * self.width + x] = value;
+    }
+
+    /// the two faces bounding cell (x, y) along the field's staggered axis - (left, right) for u
+    /// faces and (top, bottom) for v faces. centred fields have a single sample so it's returned twice
+    #[inline]
+    pub fn cell_faces(&self, x: usize, y: usize) -> (T, T) {
+        match self.stagger {
+            Stagger::Centred => (self.get(x, y), self.get(x, y)),
+            Stagger::FaceU => (self.get(x, y), self.get(x + 1, y)),
+            Stagger::FaceV => (self.get(x, y), self.get(x, y + 1)),
+        }
+    }
+
+    /// overwrites every sample without reallocating
+    pub fn fill(&mut self, value: T) {
+        self.data.fill(value);
+    }
+
+    /// exchanges storage with another field of the same shape. used to flip the advection buffers
+    /// instead of copying one into the other
+    pub fn swap(&mut self, other: &mut Field2D<T>) {
+        debug_assert!(self.width == other.width && self.height == other.height);
+        std::mem::swap(&mut self.data, &mut other.data);
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
+
+impl<T> Index<usize> for Field2D<T> {
+    type Output = [T];
+
+    #[inline]
+    fn index(&self, y: usize) -> &[T] {
+        &self.data[y * self.width..(y + 1) * self.width]
+    }
+}
+
+impl<T> IndexMut<usize> for Field2D<T> {
+    #[inline]
+    fn index_mut(&mut self, y: usize) -> &mut [T] {
+        &mut self.data[y * self.width..(y + 1) * self.width]
+    }
+}
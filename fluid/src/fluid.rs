@@ -1,17 +1,38 @@
+use crate::airfoil;
+use crate::checkpoint;
 use crate::clone;
 use crate::config;
+use crate::error;
+use crate::export;
+use crate::field;
 use crate::fluidapi;
+use crate::geometry;
+use crate::mask;
+use crate::presets;
+use crate::pressure;
+use crate::scene;
 use crate::source;
 use crate::utils;
 
-use macroquad::prelude::*;
-use std::{arch, collections::HashSet};
+use std::{arch, collections::HashSet, fs, mem, path::Path};
 
+use airfoil::Airfoil;
 use clone::Clone;
-use config::Config;
+use config::{Advection, Backtrace, Coarsening, Config, ResidualNorm, Solver};
+use error::FluidError;
+use export::npy;
+use field::Field2D;
 use fluidapi::Oo;
+use geometry::Polygon;
+use mask::{Mask, MaskRules};
+use presets::Preset;
+use pressure::{GaussSeidel, Multigrid, Pcg, PressureSolver, RedBlack, SolveReport};
+use scene::{CellKind, Directive, Scene, Shape};
 use source::Source;
-use utils::{get_color_vec, get_directions, Vector};
+use utils::{get_directions, Vector};
+
+/// halvings used to find where a back-traced path enters a wall, leaving it within 1/256 of the path
+const WALL_BISECTIONS: usize = 8;
 
 /// union enum used to store state of grid's contained elements
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -55,31 +76,69 @@ impl Ele {
     }
 }
 
+/// selects one of the staggered velocity component grids
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    /// horizontal velocity, stored on the left/right faces of each cell
+    U,
+
+    /// vertical velocity, stored on the top/bottom faces of each cell
+    V,
+}
+
 #[derive(Debug)]
 pub struct Fluid {
     pub x: usize,
     pub y: usize,
 
-    pub u: Vec<Vec<f32>>,
-    pub v: Vec<Vec<f32>>,
-    pub nu: Vec<Vec<f32>>,
-    pub nv: Vec<Vec<f32>>,
-    pub vorticity: Vec<Vec<f32>>,
+    pub u: Field2D<f32>,
+    pub v: Field2D<f32>,
+    pub nu: Field2D<f32>,
+    pub nv: Field2D<f32>,
+    pub vorticity: Field2D<f32>,
+    /// cell-centred pressure recovered by the last projection - each velocity correction scaled by
+    /// density * grid_size / dt. only Fluid cells carry one, everything else stays at zero
+    pub pressure: Field2D<f32>,
 
-    pub element: Vec<Vec<Ele>>,
+    pub element: Field2D<Ele>,
 
     pub overrelaxation: f32,
     pub iters: usize,
     pub delta_t: f32,
+    pub cfl: f32,
+    /// length of the current step - <delta_t> unless <cfl> shortens it
+    pub dt: f32,
+    /// simulated time adaptive steps are spread out to land on exactly, e.g. a batch run's next output.
+    /// fixed steps ignore it
+    pub next_stop: Option<f64>,
     pub source_velocity: f32,
     pub grid_size: f32,
     pub epsilon: f32,
+    pub density: f32,
+    pub warm_start: bool,
+    pub tolerance: f32,
+    pub residual: ResidualNorm,
+    pub solver: Solver,
+    pub coarsening: Coarsening,
+    pub threads: usize,
+    pub advection: Advection,
+    pub backtrace: Backtrace,
+    /// how the last projection went
+    pub solve: SolveReport,
 
     pub visual_modifier: f32,
     pub cell_size: f32,
 
     pub boundaries_dep: Vec<Vector<usize>>,
     pub boundaries: HashSet<Vector<usize>>,
+
+    /// simulated time in seconds. accumulated in f64 so long runs don't drift off their output cadence
+    pub time: f64,
+    /// number of full (advecting) steps taken
+    pub step: usize,
+
+    /// scene the boundaries are built from. the built-in layout is used when there isn't one
+    pub scene: Option<Scene>,
 }
 
 impl Fluid {
@@ -89,39 +148,120 @@ impl Fluid {
             y: config.y,
 
             // +1 in the x-direction to account for staggered grid
-            u: vec![vec![0.0; config.x + 1]; config.y],
+            u: Field2D::faces_u(config.x, config.y, 0.0),
             // +1 in the y-direction to account for staggered grid
-            v: vec![vec![0.0; config.x]; config.y + 1],
-            nu: vec![vec![0.0; config.x + 1]; config.y],
-            nv: vec![vec![0.0; config.x]; config.y + 1],
-            vorticity: vec![vec![0.0; config.x]; config.y],
+            v: Field2D::faces_v(config.x, config.y, 0.0),
+            nu: Field2D::faces_u(config.x, config.y, 0.0),
+            nv: Field2D::faces_v(config.x, config.y, 0.0),
+            vorticity: Field2D::construct(config.x, config.y, 0.0),
+            pressure: Field2D::construct(config.x, config.y, 0.0),
 
-            element: vec![vec![Ele::Fluid; config.x]; config.y],
+            element: Field2D::construct(config.x, config.y, Ele::Fluid),
 
             overrelaxation: config.overrelaxation,
             iters: config.iters,
             delta_t: config.delta_t,
+            cfl: config.cfl,
+            dt: config.delta_t,
+            next_stop: None,
             source_velocity: config.source_velocity,
             grid_size: config.grid_size,
             epsilon: config.epsilon,
+            density: config.density,
+            warm_start: config.warm_start,
+            tolerance: config.tolerance,
+            residual: config.residual,
+            solver: config.solver,
+            coarsening: config.coarsening,
+            threads: config.threads,
+            advection: config.advection,
+            backtrace: config.backtrace,
+            solve: SolveReport::default(),
 
             visual_modifier: config.visual_modifier,
             cell_size: config.cell_size,
 
             boundaries_dep: Vec::new(),
             boundaries: HashSet::new(),
+
+            time: 0.0,
+            step: 0,
+
+            scene: None,
+        }
+    }
+
+    /// constructs a fluid with its boundaries already asserted, from whichever of the config's scene
+    /// file, preset or the built-in layout applies
+    pub fn from_config(config: &Config) -> Result<Fluid, FluidError> {
+        if let Some(path) = &config.scene {
+            return Fluid::from_scene(config, path);
+        }
+
+        let mut fluid: Fluid = Fluid::construct(config);
+        match &config.preset {
+            Some(name) => fluid.load_preset(presets::find(name)?)?,
+            None => fluid.assert_boundary_conditions(),
+        }
+        Ok(fluid)
+    }
+
+    /// constructs a fluid and builds its boundaries from a scene file
+    pub fn from_scene(config: &Config, path: &str) -> Result<Fluid, FluidError> {
+        let mut fluid: Fluid = Fluid::construct(config);
+        fluid.set_scene(Scene::from_file(path)?)?;
+        fluid.assert_boundary_conditions();
+        Ok(fluid)
+    }
+
+    /// restores a fluid written with <save_checkpoint()>, ready to step on exactly where it left off
+    pub fn from_checkpoint(path: &str) -> Result<Fluid, FluidError> {
+        checkpoint::load(path)
+    }
+
+    /// seeds a fluid from numpy arrays, see <export::npy> for the shapes and element codes it takes.
+    /// the grid size comes from the element mask, everything else from <config>
+    pub fn from_npy(config: &Config, u_path: &str, v_path: &str, element_path: &str) -> Result<Fluid, FluidError> {
+        npy::load_fluid(config, u_path, v_path, element_path)
+    }
+
+    /// writes the complete simulation state to <path>, see <checkpoint> for the format
+    pub fn save_checkpoint(&self, path: &str) -> Result<(), FluidError> {
+        checkpoint::save(self, path)
+    }
+
+    /// replaces the current geometry and flow with a preset's. everything is reset, as with <reset()>
+    pub fn load_preset(&mut self, preset: &Preset) -> Result<(), FluidError> {
+        match preset.scene() {
+            Some(scene) => self.set_scene(scene)?,
+            None => self.scene = None,
         }
+        self.reset();
+        Ok(())
     }
 
-    /// resets all state saves of the fluid struct to in the current draw-loop
+    /// swaps in the scene used by <assert_boundary_conditions()> and <reset()>, once it's been checked
+    /// against this grid. doesn't touch the current boundaries
+    pub fn set_scene(&mut self, scene: Scene) -> Result<(), FluidError> {
+        scene.validate(self.x, self.y)?;
+        self.scene = Some(scene);
+        Ok(())
+    }
+
+    /// resets all state saves of the fluid struct to in the current draw-loop. grids are cleared in
+    /// place rather than reallocated
     pub fn reset(&mut self) {
-        self.u = vec![vec![0.0; self.x + 1]; self.y];
-        self.v = vec![vec![0.0; self.x]; self.y + 1];
-        self.nu = vec![vec![0.0; self.x + 1]; self.y];
-        self.nv = vec![vec![0.0; self.x]; self.y + 1];
-        self.element = vec![vec![Ele::Fluid; self.x]; self.y];
-        self.boundaries_dep = Vec::new();
-        self.boundaries = HashSet::new();
+        self.u.fill(0.0);
+        self.v.fill(0.0);
+        self.nu.fill(0.0);
+        self.nv.fill(0.0);
+        self.vorticity.fill(0.0);
+        self.pressure.fill(0.0);
+        self.element.fill(Ele::Fluid);
+        self.boundaries_dep.clear();
+        self.boundaries.clear();
+        self.time = 0.0;
+        self.step = 0;
         self.assert_boundary_conditions();
     }
 
@@ -134,20 +274,20 @@ impl Fluid {
         unsafe {
             arch::asm!(
                 "cmp {x}, {bx}",
-                "jae 1f",
+                "jae 2f",
                 "cmp {y}, {by}",
                 "jae 2f",
                 "mov {result}, 1",
-                "jmp 2f",
+                "jmp 3f",
                 "2:",
                 "mov {result}, 0",
-                "2:",
+                "3:",
                 x      = in(reg)       x,
                 y      = in(reg)       y,
                 bx     = in(reg)       self.x,
                 by     = in(reg)       self.y,
                 result = out(reg_byte) result,
-                options(nostack, nomem, preserves_flags),
+                options(nostack, nomem),
             );
         }
 
@@ -176,6 +316,13 @@ impl Fluid {
     /// set intial boundary conditions at start of simulation - shoud only be called
     /// one time per sim
     pub fn assert_boundary_conditions(&mut self) {
+        if let Some(scene) = self.scene.take() {
+            self.apply_scene(&scene);
+            self.scene = Some(scene);
+            self.enforce_boundary_conditions();
+            return;
+        }
+
         let _xx = self.x;
         let _yy = self.y;
 
@@ -205,13 +352,17 @@ impl Fluid {
         //     }
         // }
 
-        let circ_center = (25, 155);
+        // laid out on the default 500x500 grid and scaled to whatever size this one is
+        let across = |length: usize| length * _xx / 500;
+        let down = |length: usize| length * _yy / 500;
+        let circ_center = (across(25), down(155));
+        let radius: f32 = 65.0 * _yy as f32 / 500.0;
 
-        self.create_circle(circ_center.0, circ_center.1, 65.0);
-        self.create_circle(circ_center.0, _yy - circ_center.1, 65.0);
+        self.create_circle(circ_center.0 as f32, circ_center.1 as f32, radius);
+        self.create_circle(circ_center.0 as f32, (_yy - circ_center.1) as f32, radius);
         self.create_rectangle(0, 0, circ_center.0 * 2, circ_center.1);
         self.create_rectangle(0, _yy - circ_center.1, circ_center.0 * 2, _yy);
-        self.create_rectangle(25, _yy * 10 / 21, 50, _yy * 11 / 21);
+        self.create_rectangle(across(25), _yy * 10 / 21, across(50), _yy * 11 / 21);
 
         // add standard geometry
         // self.create_circle(_xx / 5, _yy / 2, (_yy / 11) as f32);
@@ -220,13 +371,158 @@ impl Fluid {
         self.enforce_boundary_conditions();
     }
 
+    /// paints every directive of a scene onto the grid, in order
+    fn apply_scene(&mut self, scene: &Scene) {
+        let (width, height) = (self.x, self.y);
+        let size = |length: scene::Length, extent: usize| length.resolve(extent);
+
+        for directive in &scene.directives {
+            match directive {
+                Directive::Edge(rule) => {
+                    let fill: Option<Ele> = self.cell_from_kind(rule.kind, rule.edge.inward());
+
+                    for along in rule.positions(width, height) {
+                        let (x, y) = rule.edge.cell(along, width, height);
+                        let mut oo = Oo::construct(x, y, self);
+                        match fill {
+                            Some(cell) => oo.set_here(cell),
+                            None => oo.remove_here(),
+                        }
+                    }
+                }
+                Directive::Obstacle(Shape::Circle { x, y, radius }) => {
+                    self.create_circle(size(*x, width), size(*y, height), size(*radius, height));
+                }
+                Directive::Obstacle(Shape::Rectangle { x0, y0, x1, y1 }) => {
+                    let corner = |length: &scene::Length, extent: usize| {
+                        size(*length, extent).round().clamp(0.0, extent as f32) as usize
+                    };
+                    let (x0, x1) = (corner(x0, width), corner(x1, width));
+                    let (y0, y1) = (corner(y0, height), corner(y1, height));
+                    self.create_rectangle(x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1));
+                }
+                Directive::Obstacle(Shape::Polygon { points }) => {
+                    let points: Vec<Vector<f32>> =
+                        points.iter().map(|(x, y)| Vector::construct(size(*x, width), size(*y, height))).collect();
+                    self.create_polygon(&points);
+                }
+                Directive::Obstacle(Shape::Airfoil { airfoil, x, y, chord, aoa }) => {
+                    self.create_airfoil(airfoil, size(*x, width), size(*y, height), size(*chord, width), *aoa);
+                }
+                Directive::Mask { mask, rules } => {
+                    self.apply_mask(mask, rules);
+                }
+            }
+        }
+    }
+
+    /// turns a scene's cell kind into the element to paint, None meaning plain fluid. sources without a
+    /// velocity use the configured one and clones without an offset copy from <inward>
+    fn cell_from_kind(&self, kind: CellKind, inward: Vector<isize>) -> Option<Ele> {
+        match kind {
+            CellKind::Fluid => None,
+            CellKind::Static => Some(Ele::Static),
+            CellKind::Source(velocity) => {
+                let velocity = velocity.unwrap_or(Vector::construct(self.source_velocity, 0.0));
+                Some(Ele::Source(Source::construct(velocity.x, velocity.y)))
+            }
+            CellKind::Clone(offset) => {
+                let offset = offset.unwrap_or(inward);
+                Some(Ele::Clone(Clone::construct(offset.x, offset.y)))
+            }
+        }
+    }
+
+    /// paints cells from a bitmap mask stretched over the whole grid. cells the mask maps to plain fluid
+    /// are left as they were, so a mask can be layered on top of edges and shapes
+    pub fn apply_mask(&mut self, mask: &Mask, rules: &MaskRules) {
+        let (width, height) = (self.x, self.y);
+        for y in 0..height {
+            for x in 0..width {
+                let Some(kind) = mask.classify(rules, x, y, width, height) else {
+                    continue;
+                };
+
+                // masks have no edge to point inward from - clones without offsets are rejected when parsed
+                let cell = self.cell_from_kind(kind, Vector::construct(0, 0));
+                let mut oo = Oo::construct(x, y, self);
+                match cell {
+                    Some(cell) => oo.set_here(cell),
+                    None => oo.remove_here(),
+                }
+            }
+        }
+    }
+
+    /// the element grid as a one pixel per cell mask: white fluid, black walls and a distinct colour for
+    /// every different source velocity and clone offset, with the rules that map those colours back
+    pub fn geometry_mask(&self) -> (Mask, MaskRules) {
+        // easy to pick out in an image editor for the first few, then anything that isn't grey
+        const PALETTE: [u32; 6] = [0xff0000, 0x00ff00, 0x0000ff, 0xffff00, 0xff00ff, 0x00ffff];
+
+        let mut mask = Mask::construct(self.x, self.y, [255; 4]);
+        let mut rules = MaskRules::default();
+        for y in 0..self.y {
+            for x in 0..self.x {
+                let kind = match self.element[y][x] {
+                    Ele::Fluid => continue,
+                    Ele::Static => {
+                        mask.pixels[y * self.x + x] = [0, 0, 0, 255];
+                        continue;
+                    }
+                    Ele::Source(source) => CellKind::Source(Some(source.velocity)),
+                    Ele::Clone(clone) => CellKind::Clone(Some(clone.master)),
+                };
+
+                let colour = match rules.colours.iter().find(|(_, existing)| *existing == kind) {
+                    Some((colour, _)) => *colour,
+                    None => {
+                        let idx = rules.colours.len();
+                        let colour = PALETTE.get(idx).copied().unwrap_or(0x800000 | idx as u32);
+                        rules.colours.push((colour, kind));
+                        colour
+                    }
+                };
+                mask.pixels[y * self.x + x] = [(colour >> 16) as u8, (colour >> 8) as u8, colour as u8, 255];
+            }
+        }
+
+        (mask, rules)
+    }
+
+    /// saves the current geometry, hand-drawn walls included, as a scene file at <path> plus a png mask
+    /// next to it with the same name. the saved scene becomes this fluid's scene, so <reset()> brings
+    /// the geometry back instead of wiping it
+    pub fn save_geometry(&mut self, path: &str) -> Result<(), FluidError> {
+        let scene_path = Path::new(path);
+        let mask_path = scene_path.with_extension("png");
+        let mask_name = mask_path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+
+        let (mask, rules) = self.geometry_mask();
+        mask.write_png(&mask_path.to_string_lossy())?;
+
+        let mut text = format!("# geometry saved from a {}x{} grid\nmask {}", self.x, self.y, mask_name);
+        for (colour, kind) in &rules.colours {
+            text.push_str(&format!(" colour {:06x} {}", colour, kind));
+        }
+        text.push('\n');
+        fs::write(scene_path, text).map_err(|err| FluidError::Io { path: path.to_string(), reason: err.to_string() })?;
+
+        self.set_scene(Scene::from_file(path)?)
+    }
+
+    /// loads geometry saved with <save_geometry()>, or any other scene file, and resets onto it
+    pub fn load_geometry(&mut self, path: &str) -> Result<(), FluidError> {
+        self.set_scene(Scene::from_file(path)?)?;
+        self.reset();
+        Ok(())
+    }
+
     /// places circular geometry at a location in the simulation
-    #[allow(dead_code)]
-    fn create_circle(&mut self, center_x: usize, center_y: usize, radius: f32) {
+    pub fn create_circle(&mut self, center_x: f32, center_y: f32, radius: f32) {
         for y in 0..self.y {
             for x in 0..self.x {
-                if (x as f32 - center_x as f32).powf(2.0) + (y as f32 - center_y as f32).powf(2.0)
-                    < radius.powf(2.0)
+                if (x as f32 - center_x).powf(2.0) + (y as f32 - center_y).powf(2.0) < radius.powf(2.0)
                 {
                     let mut oo = Oo::construct(x, y, self);
                     oo.set_here(Ele::Static);
@@ -235,17 +531,61 @@ impl Fluid {
         }
     }
 
-    /// places rectangular geometry in the simulation
-    #[allow(dead_code)]
-    fn create_rectangle(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
-        for y in y0..y1 {
-            for x in x0..x1 {
+    /// places rectangular geometry in the simulation. the far corner is exclusive and clipped to the grid
+    pub fn create_rectangle(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        for y in y0..y1.min(self.y) {
+            for x in x0..x1.min(self.x) {
                 let mut oo = Oo::construct(x, y, self);
                 oo.set_here(Ele::Static);
             }
         }
     }
 
+    /// fills every cell whose centre lies inside a closed polygon, using the even-odd rule so self
+    /// intersecting outlines still behave predictably. vertices are in cells
+    pub fn create_polygon(&mut self, points: &[Vector<f32>]) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let min_y = points.iter().fold(f32::INFINITY, |min, point| min.min(point.y));
+        let max_y = points.iter().fold(f32::NEG_INFINITY, |max, point| max.max(point.y));
+        let y0 = min_y.floor().clamp(0.0, self.y as f32) as usize;
+        let y1 = (max_y.ceil() + 1.0).clamp(0.0, self.y as f32) as usize;
+
+        // scanline fill: for each row of cell centres find where the outline crosses it, then fill
+        // between every other pair of crossings
+        let mut crossings: Vec<f32> = Vec::new();
+        for y in y0..y1 {
+            let cy = y as f32 + 0.5;
+            crossings.clear();
+            for idx in 0..points.len() {
+                let a = points[idx];
+                let b = points[(idx + 1) % points.len()];
+                if (a.y <= cy) != (b.y <= cy) {
+                    crossings.push(a.x + (cy - a.y) / (b.y - a.y) * (b.x - a.x));
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+
+            for pair in crossings.chunks_exact(2) {
+                let x0 = (pair[0] - 0.5).ceil().clamp(0.0, self.x as f32) as usize;
+                let x1 = ((pair[1] - 0.5).floor() + 1.0).clamp(0.0, self.x as f32) as usize;
+                for x in x0..x1 {
+                    let mut oo = Oo::construct(x, y, self);
+                    oo.set_here(Ele::Static);
+                }
+            }
+        }
+    }
+
+    /// rasterises <airfoil> with its leading edge at (x, y) and the given chord in cells, pitched nose
+    /// up by <aoa> degrees about the quarter chord
+    pub fn create_airfoil(&mut self, airfoil: &Airfoil, x: f32, y: f32, chord: f32, aoa: f32) {
+        let outline: Polygon = airfoil.placed(x, y, chord, aoa);
+        self.create_polygon(&outline.points);
+    }
+
     #[allow(dead_code)]
     fn fill_top_border(&mut self, fill: Ele) {
         for x in 0..self.x {
@@ -284,7 +624,7 @@ impl Fluid {
     /// and thus the more the merrier in terms of speed
     pub fn fill_dfs(&mut self, x: usize, y: usize) {
         let mut stack = vec![(x, y)];
-        let mut seen = vec![vec![false; self.x]; self.y];
+        let mut seen = Field2D::construct(self.x, self.y, false);
 
         while let Some((x, y)) = stack.pop() {
             if self.element[y][x] == Ele::Static || seen[y][x] {
@@ -331,215 +671,410 @@ impl Fluid {
         }
     }
 
-    #[allow(clippy::too_many_arguments)]
-    pub fn display(
-        &mut self, head: bool, draw_bounds: bool, normalize: bool, thickness: f32, head_size: f32,
-        spacing: usize, vector: bool, fill: bool,
-    ) {
+    /// traces a streamline from a seed point in grid coordinates, stopping once it leaves the grid
+    pub fn compute_streamline(&self, seed: Vector<f32>, max_steps: usize, step_size: f32) -> Vec<Vector<f32>> {
+        let mut streamline = Vec::new();
+        let (mut x, mut y) = (seed.x, seed.y);
+
+        for _ in 0..max_steps {
+            streamline.push(Vector::construct(x, y));
+            let velocity = self.sample_velocity(x, y);
+
+            x += velocity.x * step_size;
+            y += velocity.y * step_size;
+
+            if !self.inbounds(x as usize, y as usize) {
+                break;
+            }
+        }
+
+        streamline
+    }
+
+    pub fn update_fluid(&mut self, project: bool, advect: bool, enforce_bc: bool, vort_confinement: bool) {
+        if advect {
+            self.dt = self.next_step_size();
+            self.advect_velocity();
+            // only advection moves the fluid forward in time - the other stages can be run on their
+            // own for debugging without the clock ticking
+            self.time += self.dt as f64;
+            self.step += 1;
+        }
+        if vort_confinement {
+            self.apply_vorticity_confinement();
+        }
+        if enforce_bc {
+            self.enforce_boundary_conditions();
+        }
+        if project {
+            // projection is done last in order to maintain clearest view as display will be called after this
+            self.projection();
+        }
+    }
+
+    /// net outflow of a cell, read straight off its faces
+    pub fn divergence_at(&self, x: usize, y: usize) -> f32 {
+        let (left, right) = self.u.cell_faces(x, y);
+        let (top, bottom) = self.v.cell_faces(x, y);
+        right - left + bottom - top
+    }
+
+    /// largest absolute divergence over all Fluid cells - what projection is trying to drive to zero
+    pub fn max_divergence(&self) -> f32 {
+        let mut max: f32 = 0.0;
         for y in 0..self.y {
             for x in 0..self.x {
-                let color: Color = match self.element[y][x] {
-                    Ele::Fluid => continue,
-                    Ele::Static => Color::from_hex(0x000000),
-                    Ele::Source(_) => Color::from_hex(0x1b85b8),
-                    Ele::Clone(_) => Color::from_hex(0x559e83),
-                };
-                draw_rectangle(
-                    x as f32 * self.cell_size,
-                    y as f32 * self.cell_size,
-                    self.cell_size,
-                    self.cell_size,
-                    color,
-                );
+                if self.element[y][x] == Ele::Fluid {
+                    max = max.max(self.divergence_at(x, y).abs());
+                }
             }
         }
+        max
+    }
+
+    /// divergence over the Fluid cells with at least one fluid neighbour - cells walled in on every
+    /// side can't be corrected, so they'd only hold the solve at its cap
+    pub fn residual(&self, norm: ResidualNorm) -> f32 {
+        let (mut max, mut squares, mut cells): (f32, f64, usize) = (0.0, 0.0, 0);
         for y in 0..self.y {
             for x in 0..self.x {
-                if !draw_bounds && self.element[y][x] != Ele::Fluid {
+                if self.element[y][x] != Ele::Fluid {
                     continue;
                 }
-                if x % spacing != 0 || y % spacing != 0 {
+                let open = get_directions().into_iter().any(|(dx, dy)| {
+                    let (nx, ny) = (x as isize + dx, y as isize + dy);
+                    nx >= 0
+                        && ny >= 0
+                        && self.inbounds(nx as usize, ny as usize)
+                        && self.element[ny as usize][nx as usize].is_fluid()
+                });
+                if !open {
                     continue;
                 }
 
-                let oo: Oo = Oo::construct(x, y, self);
-                let mut velocity = Vector::new();
-                velocity.add(oo.peek_velocity(1, 0), 0.0);
-                velocity.add(oo.peek_velocity(-1, 0), 0.0);
-                velocity.add(0.0, oo.peek_velocity(0, 1));
-                velocity.add(0.0, oo.peek_velocity(0, -1));
-
-                let color: Color =
-                    get_color_vec(&velocity, oo.fluid.source_velocity, oo.fluid.visual_modifier);
-
-                if vector {
-                    let nsize = self.cell_size * 2.5;
-                    if normalize {
-                        velocity.normalize();
-                        velocity.x *= nsize;
-                        velocity.y *= nsize;
-                    }
-
-                    let start: Vector<f32> = Vector {
-                        x: x as f32 * self.cell_size + self.cell_size / 2.0,
-                        y: y as f32 * self.cell_size + self.cell_size / 2.0,
-                    };
-                    draw_line(start.x, start.y, start.x + velocity.x, start.y + velocity.y, thickness, color);
-
-                    if head {
-                        draw_circle(start.x + velocity.x, start.y + velocity.y, head_size, color);
-                    }
-                }
-                else if fill {
-                    let start: Vector<f32> =
-                        Vector { x: x as f32 * self.cell_size, y: y as f32 * self.cell_size };
-                    draw_rectangle(start.x, start.y, self.cell_size, self.cell_size, color);
-                }
+                let divergence: f32 = self.divergence_at(x, y);
+                max = max.max(divergence.abs());
+                squares += (divergence as f64).powi(2);
+                cells += 1;
             }
         }
+
+        match norm {
+            ResidualNorm::Max => max,
+            ResidualNorm::Rms if cells == 0 => 0.0,
+            ResidualNorm::Rms => (squares / cells as f64).sqrt() as f32,
+        }
     }
 
-    pub fn streamline(
-        &self, spacing_x: usize, spacing_y: usize, max_steps: usize, step_size: f32, thickness: f32,
-    ) {
-        let mut streamlines: Vec<Vec<Vector<f32>>> = Vec::new();
+    /// largest absolute face velocity on either grid
+    pub fn max_speed(&self) -> f32 {
+        self.u.as_slice().iter().chain(self.v.as_slice()).fold(0.0, |max: f32, vel| max.max(vel.abs()))
+    }
 
-        for y in (0..self.y).step_by(spacing_y) {
-            for x in (0..self.x).step_by(spacing_x) {
-                let seed = Vector::construct(x as f32, y as f32);
-                let streamline = self.compute_streamline(seed, max_steps, step_size);
-                streamlines.push(streamline);
-            }
+    /// false once any face velocity has gone to inf or nan, i.e. the sim has blown up
+    pub fn is_finite(&self) -> bool {
+        self.u.as_slice().iter().chain(self.v.as_slice()).all(|vel| vel.is_finite())
+    }
+
+    /// pressure that one unit of velocity correction on a cell's faces stands for
+    pub fn pressure_scale(&self) -> f32 {
+        self.density * self.grid_size / self.dt
+    }
+
+    /// length of the next step. always <delta_t> without a <cfl>, otherwise the longest step that keeps
+    /// the fastest face within <cfl> cells, capped at <delta_t> and evened out so one of them lands on
+    /// <next_stop>
+    pub fn next_step_size(&self) -> f32 {
+        if self.cfl == 0.0 {
+            return self.delta_t;
         }
 
-        for streamline in streamlines {
-            let len = streamline.len();
-            if len < 1 {
-                continue;
-            }
-            for idx in 0..(len - 1) {
-                draw_line(
-                    streamline[idx].x * self.cell_size,
-                    streamline[idx].y * self.cell_size,
-                    streamline[idx + 1].x * self.cell_size,
-                    streamline[idx + 1].y * self.cell_size,
-                    thickness,
-                    WHITE,
-                );
+        let speed: f32 = self.max_speed();
+        let mut dt: f32 = self.delta_t;
+        if speed > 0.0 {
+            dt = dt.min(self.cfl * self.grid_size / speed);
+        }
+        if let Some(stop) = self.next_stop {
+            let left: f64 = stop - self.time;
+            // a sliver this small is only rounding left over from landing on the last stop
+            if left > dt as f64 * 1e-3 {
+                let steps: f64 = (left / dt as f64).ceil();
+                dt = (left / steps) as f32;
             }
         }
+        dt
     }
 
-    fn compute_streamline(&self, seed: Vector<f32>, max_steps: usize, step_size: f32) -> Vec<Vector<f32>> {
-        let mut streamline = Vec::new();
-        let (mut x, mut y) = (seed.x, seed.y);
-
-        for _ in 0..max_steps {
-            streamline.push(Vector::construct(x, y));
-            let u = self.double_lin_int(x, y, "u");
-            let v = self.double_lin_int(x, y, "v");
-
-            x += u * step_size;
-            y += v * step_size;
+    /// pushes the current pressure back through the faces, i.e. replays a whole earlier solve in one sweep.
+    /// face updates are linear so this lands exactly where those iterations would have
+    fn apply_pressure(&mut self) {
+        let scale: f32 = self.pressure_scale();
+        for y in 0..self.y {
+            for x in 0..self.x {
+                let pressure: f32 = self.pressure[y][x];
+                if self.element[y][x] != Ele::Fluid || pressure == 0.0 {
+                    continue;
+                }
 
-            if !self.inbounds(x as usize, y as usize) {
-                break;
+                Oo::construct(x, y, self).modify_adjacent(pressure / scale);
             }
         }
-
-        streamline
     }
 
-    pub fn update_fluid(&mut self, project: bool, advect: bool, enforce_bc: bool, vort_confinement: bool) {
-        if advect {
-            self.semi_lagrangian_advection();
+    fn projection(&mut self) {
+        match self.solver {
+            Solver::GaussSeidel => self.project_with(&mut GaussSeidel),
+            Solver::RedBlack => self.project_with(&mut RedBlack::construct(self.threads)),
+            Solver::Pcg => self.project_with(&mut Pcg),
+            Solver::Multigrid => self.project_with(&mut Multigrid::construct(self.coarsening)),
         }
-        if vort_confinement {
-            self.apply_vorticity_confinement();
+    }
+
+    /// one projection with any pressure solver, configured or not. warm starting happens here so every
+    /// solver gets it the same way
+    pub fn project_with(&mut self, solver: &mut dyn PressureSolver) {
+        if self.warm_start {
+            self.apply_pressure();
         }
-        if enforce_bc {
-            self.enforce_boundary_conditions();
-        }
-        if project {
-            // projection is done last in order to maintain clearest view as display will be called after this
-            self.projection_gauss_seidel();
+        else {
+            self.pressure.fill(0.0);
         }
+
+        self.solve = solver.solve(self);
     }
 
-    fn projection_gauss_seidel(&mut self) {
-        for _ in 0..self.iters {
-            for y in 0..self.y {
-                for x in 0..self.x {
-                    if self.element[y][x] != Ele::Fluid {
+    /// net pressure force per unit depth on the Static cells inside the cell rectangle [x0, x1) x [y0, y1),
+    /// e.g. lift and drag on an obstacle from a box drawn around it. y points down like the grid
+    pub fn pressure_force(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> Vector<f32> {
+        let mut force: Vector<f32> = Vector::construct(0.0, 0.0);
+        for y in y0..y1.min(self.y) {
+            for x in x0..x1.min(self.x) {
+                if !self.element[y][x].is_static() {
+                    continue;
+                }
+
+                // every face shared with fluid is pushed on from the fluid's side
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    let (nx, ny) = (x as isize + dx, y as isize + dy);
+                    if nx < 0 || ny < 0 || nx as usize >= self.x || ny as usize >= self.y {
                         continue;
                     }
-
-                    let mut oo: Oo = Oo::construct(x, y, self);
-
-                    let divergence: f32 = oo.divergence_here();
-                    let sides: f32 = oo.afflicted_area();
-
-                    if sides == 0.0 {
+                    if self.element[ny as usize][nx as usize] != Ele::Fluid {
                         continue;
                     }
 
-                    let correction: f32 = -divergence / sides * oo.fluid.overrelaxation;
-                    oo.modify_adjacent(correction);
+                    let push: f32 = self.pressure[ny as usize][nx as usize] * self.grid_size;
+                    force.x -= push * dx as f32;
+                    force.y -= push * dy as f32;
                 }
             }
         }
+        force
     }
 
-    fn semi_lagrangian_advection(&mut self) {
-        let dt: f32 = self.delta_t;
-        let size: f32 = self.grid_size;
+    fn advect_velocity(&mut self) {
+        let dt: f32 = self.dt;
+
+        // the scratch grids are moved out while they're written so the velocities stay readable
+        let mut nu: Field2D<f32> = mem::replace(&mut self.nu, Field2D::construct(0, 0, 0.0));
+        let mut nv: Field2D<f32> = mem::replace(&mut self.nv, Field2D::construct(0, 0, 0.0));
+
+        self.semi_lagrangian(&self.u, &self.v, dt, &mut nu, &mut nv);
+        match self.advection {
+            Advection::SemiLagrangian => {}
+            Advection::MacCormack => {
+                let mut back_u: Field2D<f32> = Field2D::faces_u(self.x, self.y, 0.0);
+                let mut back_v: Field2D<f32> = Field2D::faces_v(self.x, self.y, 0.0);
+                self.semi_lagrangian(&nu, &nv, -dt, &mut back_u, &mut back_v);
+
+                // half the error of the round trip is taken off the forward step
+                for (field, forward, back) in [(Field::U, &mut nu, &back_u), (Field::V, &mut nv, &back_v)] {
+                    let original: &Field2D<f32> = self.field(field);
+                    self.each_advected(field, |j, i| {
+                        forward[i][j] += 0.5 * (original[i][j] - back[i][j]);
+                    });
+                }
+                self.limit(&mut nu, &mut nv);
+            }
+            Advection::Bfecc => {
+                let mut back_u: Field2D<f32> = Field2D::faces_u(self.x, self.y, 0.0);
+                let mut back_v: Field2D<f32> = Field2D::faces_v(self.x, self.y, 0.0);
+                self.semi_lagrangian(&nu, &nv, -dt, &mut back_u, &mut back_v);
+
+                // the same error estimate, taken off the starting velocities before a second forward step
+                let (mut start_u, mut start_v) = (self.u.clone(), self.v.clone());
+                for (field, start, back) in [(Field::U, &mut start_u, &back_u), (Field::V, &mut start_v, &back_v)] {
+                    self.each_advected(field, |j, i| {
+                        let error: f32 = start[i][j] - back[i][j];
+                        start[i][j] += 0.5 * error;
+                    });
+                }
+                self.semi_lagrangian(&start_u, &start_v, dt, &mut nu, &mut nv);
+                self.limit(&mut nu, &mut nv);
+            }
+        }
+
+        self.nu = nu;
+        self.nv = nv;
+        self.u.swap(&mut self.nu);
+        self.v.swap(&mut self.nv);
+    }
 
-        // iterates though cells sans border
+    /// calls <each> with (x, y) of every face of <field> that advection moves - everything off the
+    /// border that doesn't touch a Static cell. the rest are left at zero
+    fn each_advected(&self, field: Field, mut each: impl FnMut(usize, usize)) {
         for i in 1..self.y {
             for j in 1..self.x {
-                // u component
-                if i < self.y - 1 && !self.element[i][j - 1].is_static() && !self.element[i][j].is_static() {
-                    let u = self.u[i][j];
-                    let v = self.average_v(j, i);
+                let advected = match field {
+                    Field::U => i < self.y - 1 && !self.element[i][j - 1].is_static(),
+                    Field::V => j < self.x - 1 && !self.element[i - 1][j].is_static(),
+                };
+                if advected && !self.element[i][j].is_static() {
+                    each(j, i);
+                }
+            }
+        }
+    }
+
+    /// where the current velocities carry face (x, y) of <field> from over <dt>, in cells. a negative
+    /// <dt> traces forward instead. points that would land inside a wall are pulled back out of it
+    fn departure(&self, field: Field, x: usize, y: usize, dt: f32) -> (f32, f32) {
+        let size: f32 = self.grid_size;
+        let (u, v, px, py) = match field {
+            Field::U => (self.u[y][x], self.average_v(x, y), x as f32, y as f32 + 0.5),
+            Field::V => (self.average_u(x, y), self.v[y][x], x as f32 + 0.5, y as f32),
+        };
 
-                    let mut x = j as f32;
-                    let mut y = i as f32 + 0.5;
+        // every stage after the first samples the velocity part of the way back along the last one
+        let step: f32 = dt / size;
+        let stage = |(du, dv): (f32, f32), fraction: f32| -> (f32, f32) {
+            let velocity = self.sample_velocity(px - du * step * fraction, py - dv * step * fraction);
+            (velocity.x, velocity.y)
+        };
+        let (dx, dy) = match self.backtrace {
+            Backtrace::Euler => (u * dt / size, v * dt / size),
+            Backtrace::Rk2 => {
+                let k2 = stage((u, v), 0.5);
+                (k2.0 * step, k2.1 * step)
+            }
+            Backtrace::Rk3 => {
+                let k2 = stage((u, v), 0.5);
+                let k3 = stage(k2, 0.75);
+                (
+                    (2.0 * u + 3.0 * k2.0 + 4.0 * k3.0) / 9.0 * step,
+                    (2.0 * v + 3.0 * k2.1 + 4.0 * k3.1) / 9.0 * step,
+                )
+            }
+            Backtrace::Rk4 => {
+                let k2 = stage((u, v), 0.5);
+                let k3 = stage(k2, 0.5);
+                let k4 = stage(k3, 1.0);
+                (
+                    (u + 2.0 * k2.0 + 2.0 * k3.0 + k4.0) / 6.0 * step,
+                    (v + 2.0 * k2.1 + 2.0 * k3.1 + k4.1) / 6.0 * step,
+                )
+            }
+        };
 
-                    x -= u * dt / size;
-                    y -= v * dt / size;
+        self.clear_of_walls((px, py), (px - dx, py - dy))
+    }
 
-                    self.nu[i][j] = self.double_lin_int(x, y, "u");
-                }
+    /// <to> unless it's inside a Static cell, otherwise the furthest point on the line from <from> that
+    /// isn't. <from> is a face off any wall, so the search always has somewhere to land
+    fn clear_of_walls(&self, from: (f32, f32), to: (f32, f32)) -> (f32, f32) {
+        let along = |fraction: f32| (from.0 + (to.0 - from.0) * fraction, from.1 + (to.1 - from.1) * fraction);
+        let in_wall = |(x, y): (f32, f32)| {
+            // past the edge counts as open, interpolation clamps those points onto the grid anyway
+            x >= 0.0
+                && y >= 0.0
+                && self.inbounds(x as usize, y as usize)
+                && self.element[y as usize][x as usize].is_static()
+        };
+        if !in_wall(to) {
+            return to;
+        }
 
-                // v component
-                if j < self.x - 1 && !self.element[i - 1][j].is_static() && !self.element[i][j].is_static() {
-                    let u = self.average_u(j, i);
-                    let v = self.v[i][j];
+        let (mut open, mut wall): (f32, f32) = (0.0, 1.0);
+        for _ in 0..WALL_BISECTIONS {
+            let middle: f32 = 0.5 * (open + wall);
+            if in_wall(along(middle)) {
+                wall = middle;
+            }
+            else {
+                open = middle;
+            }
+        }
+        along(open)
+    }
 
-                    let mut x = j as f32 + 0.5;
-                    let mut y = i as f32;
+    /// one first order step of <source_u> and <source_v> into <out_u> and <out_v>, each face sampled
+    /// at its departure point over <dt>
+    fn semi_lagrangian(
+        &self,
+        source_u: &Field2D<f32>,
+        source_v: &Field2D<f32>,
+        dt: f32,
+        out_u: &mut Field2D<f32>,
+        out_v: &mut Field2D<f32>,
+    ) {
+        for (field, source, out) in [(Field::U, source_u, out_u), (Field::V, source_v, out_v)] {
+            // faces that aren't advected are zeroed, so the buffer can be swapped in afterwards
+            // without carrying stale values from two steps back
+            out.fill(0.0);
+            self.each_advected(field, |j, i| {
+                let (x, y) = self.departure(field, j, i, dt);
+                out[i][j] = self.interpolate(source, x, y);
+            });
+        }
+    }
 
-                    x -= u * dt / size;
-                    y -= v * dt / size;
+    /// clamps every advected face to the range of the four velocities interpolated at its departure
+    /// point, so the error correction can't create new extremes next to sharp gradients
+    fn limit(&self, out_u: &mut Field2D<f32>, out_v: &mut Field2D<f32>) {
+        for (field, out) in [(Field::U, out_u), (Field::V, out_v)] {
+            self.each_advected(field, |j, i| {
+                let (x, y) = self.departure(field, j, i, self.dt);
+                let (corners, _, _) = self.stencil(self.field(field), x, y);
+                let low: f32 = corners.iter().copied().fold(f32::INFINITY, f32::min);
+                let high: f32 = corners.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                out[i][j] = out[i][j].clamp(low, high);
+            });
+        }
+    }
 
-                    self.nv[i][j] = self.double_lin_int(x, y, "v");
-                }
-            }
+    /// returns the grid backing a velocity component
+    pub fn field(&self, field: Field) -> &Field2D<f32> {
+        match field {
+            Field::U => &self.u,
+            Field::V => &self.v,
         }
+    }
 
-        self.u.clone_from(&self.nu);
-        self.v.clone_from(&self.nv);
+    /// bilinearly interpolated velocity at an arbitrary point. coordinates are in cells with the origin
+    /// at the top-left corner of the grid, and points outside the grid are clamped to its edge
+    pub fn sample_velocity(&self, x: f32, y: f32) -> Vector<f32> {
+        Vector::construct(self.double_lin_int(x, y, Field::U), self.double_lin_int(x, y, Field::V))
     }
 
-    fn double_lin_int(&self, x: f32, y: f32, field: &str) -> f32 {
-        let (field, dx, dy): (&Vec<Vec<f32>>, f32, f32) = match field {
-            "u" => (&self.u, 0.0, 0.5),
-            "v" => (&self.v, 0.5, 0.0),
-            _ => {
-                eprintln!("Error in field token");
-                std::process::exit(99);
-            }
-        };
+    fn double_lin_int(&self, x: f32, y: f32, field: Field) -> f32 {
+        self.interpolate(self.field(field), x, y)
+    }
+
+    /// bilinear interpolation of any grid shaped like <u> or <v>, clamped to the grid like
+    /// <sample_velocity()>
+    fn interpolate(&self, field: &Field2D<f32>, x: f32, y: f32) -> f32 {
+        let ([s00, s10, s11, s01], tx, ty) = self.stencil(field, x, y);
+
+        let sx = 1.0 - tx;
+        let sy = 1.0 - ty;
+
+        sx * sy * s00 + tx * sy * s10 + tx * ty * s11 + sx * ty * s01
+    }
+
+    /// the four samples around a point - top left, top right, bottom right, bottom left - and the
+    /// point's fractional position between them
+    fn stencil(&self, field: &Field2D<f32>, x: f32, y: f32) -> ([f32; 4], f32, f32) {
+        let (dx, dy) = field.stagger.offset();
 
         let x = (x - dx).clamp(0.0, (self.x - 1) as f32);
         let y = (y - dy).clamp(0.0, (self.y - 1) as f32);
@@ -552,10 +1087,7 @@ impl Fluid {
         let tx = x - x0 as f32;
         let ty = y - y0 as f32;
 
-        let sx = 1.0 - tx;
-        let sy = 1.0 - ty;
-
-        sx * sy * field[y0][x0] + tx * sy * field[y0][x1] + tx * ty * field[y1][x1] + sx * ty * field[y1][x0]
+        ([field[y0][x0], field[y0][x1], field[y1][x1], field[y1][x0]], tx, ty)
     }
 
     fn average_u(&self, x: usize, y: usize) -> f32 {
@@ -643,8 +1175,8 @@ impl Fluid {
                 options(nostack)
             );
 
-            self.u[i][j] += force_x * self.delta_t;
-            self.v[i][j] += force_y * self.delta_t;
+            self.u[i][j] += force_x * self.dt;
+            self.v[i][j] += force_y * self.dt;
         }
     }
 
@@ -665,8 +1197,8 @@ impl Fluid {
                     let force_x: f32 = self.epsilon * (ny * self.vorticity[i][j]);
                     let force_y: f32 = -self.epsilon * (nx * self.vorticity[i][j]);
 
-                    self.u[i][j] += force_x * self.delta_t;
-                    self.v[i][j] += force_y * self.delta_t;
+                    self.u[i][j] += force_x * self.dt;
+                    self.v[i][j] += force_y * self.dt;
                 }
             }
         }
@@ -740,10 +1272,167 @@ impl Fluid {
                     oo.set_velocity_polarized(sour.velocity.x, sour.velocity.y);
                 }
                 Ele::Clone(clo) => {
-                    oo.set_velocity_matched(clo.master.x, clo.master.y);
+                    // a master outside the grid has nothing to copy, so the cell is left as is
+                    let _ = oo.set_velocity_matched(clo.master.x, clo.master.y);
                 }
                 _ => {}
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sized;
+
+    /// <size> x <size> box walled in on every side, at rest
+    fn closed_box(size: usize, obstacles: &str) -> Fluid {
+        let text = format!("edge left static\nedge right static\nedge top static\nedge bottom static\n{}", obstacles);
+        let mut fluid: Fluid = Fluid::construct(&sized(size, size));
+        fluid.set_scene(Scene::parse("box", &text).unwrap()).unwrap();
+        fluid.assert_boundary_conditions();
+        fluid
+    }
+
+    /// sets each face to <velocity> at the face's position in cells, then zeroes the wall faces again
+    fn set_velocity(fluid: &mut Fluid, velocity: impl Fn(f32, f32) -> (f32, f32)) {
+        for y in 0..fluid.y {
+            for x in 0..=fluid.x {
+                fluid.u[y][x] = velocity(x as f32, y as f32 + 0.5).0;
+            }
+        }
+        for y in 0..=fluid.y {
+            for x in 0..fluid.x {
+                fluid.v[y][x] = velocity(x as f32 + 0.5, y as f32).1;
+            }
+        }
+        fluid.enforce_boundary_conditions();
+    }
+
+    /// spinning disc of fluid in the middle of a still box, turning <radians> per step. the velocity
+    /// drops straight to zero at its rim
+    fn spinning_disc(advection: Advection, radians: f32) -> Fluid {
+        let mut fluid: Fluid = closed_box(40, "");
+        fluid.advection = advection;
+        let spin: f32 = radians * fluid.grid_size / fluid.delta_t;
+        set_velocity(&mut fluid, |x, y| {
+            let (dx, dy) = (x - 20.0, y - 20.0);
+            if dx * dx + dy * dy < 100.0 { (-spin * dy, spin * dx) } else { (0.0, 0.0) }
+        });
+        fluid
+    }
+
+    fn kinetic_energy(fluid: &Fluid) -> f64 {
+        let squares = |field: &Field2D<f32>| field.as_slice().iter().map(|face| (*face as f64).powi(2)).sum::<f64>();
+        squares(&fluid.u) + squares(&fluid.v)
+    }
+
+    #[test]
+    fn corrected_faces_stay_inside_their_departure_stencil() {
+        for advection in [Advection::MacCormack, Advection::Bfecc] {
+            let mut fluid: Fluid = spinning_disc(advection, 0.3);
+            fluid.dt = fluid.delta_t;
+
+            // the range of the starting velocities around each face's departure point
+            let mut bounds: Vec<(Field, usize, usize, f32, f32)> = Vec::new();
+            for field in [Field::U, Field::V] {
+                fluid.each_advected(field, |j, i| {
+                    let (x, y) = fluid.departure(field, j, i, fluid.dt);
+                    let (corners, _, _) = fluid.stencil(fluid.field(field), x, y);
+                    let low: f32 = corners.iter().copied().fold(f32::INFINITY, f32::min);
+                    let high: f32 = corners.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                    bounds.push((field, j, i, low, high));
+                });
+            }
+
+            fluid.advect_velocity();
+            for (field, j, i, low, high) in bounds {
+                let face: f32 = fluid.field(field)[i][j];
+                let at = format!("{:?} {:?} face ({}, {})", advection, field, j, i);
+                assert!(low <= face && face <= high, "{} = {} outside {}..{}", at, face, low, high);
+            }
+        }
+    }
+
+    #[test]
+    fn corrected_schemes_keep_more_kinetic_energy() {
+        let energy_after = |advection: Advection| -> f64 {
+            let mut fluid: Fluid = spinning_disc(advection, 0.2);
+            for _ in 0..20 {
+                fluid.update_fluid(true, true, true, false);
+            }
+            kinetic_energy(&fluid)
+        };
+
+        let start: f64 = kinetic_energy(&spinning_disc(Advection::SemiLagrangian, 0.2));
+        let semi: f64 = energy_after(Advection::SemiLagrangian);
+        let maccormack: f64 = energy_after(Advection::MacCormack);
+        let bfecc: f64 = energy_after(Advection::Bfecc);
+        assert!(semi < start);
+        assert!(maccormack > semi * 1.25, "maccormack {} vs semi-lagrangian {}", maccormack, semi);
+        assert!(bfecc > semi * 1.25, "bfecc {} vs semi-lagrangian {}", bfecc, semi);
+    }
+    const BACKTRACES: [Backtrace; 4] = [Backtrace::Euler, Backtrace::Rk2, Backtrace::Rk3, Backtrace::Rk4];
+
+    #[test]
+    fn departures_never_land_in_a_wall() {
+        // fast swirling flow around a cylinder, so plenty of traces pass through it within a step
+        let mut fluid: Fluid = closed_box(48, "circle 50% 50% 7");
+        let speed: f32 = 5.0 * fluid.grid_size / fluid.delta_t;
+        set_velocity(&mut fluid, |x, y| (speed + 0.3 * speed * (y - 24.0).sin(), 0.4 * speed * (x * 0.3).cos()));
+
+        for backtrace in BACKTRACES {
+            fluid.backtrace = backtrace;
+            let mut traced: usize = 0;
+            for dt in [fluid.delta_t, -fluid.delta_t] {
+                for field in [Field::U, Field::V] {
+                    fluid.each_advected(field, |j, i| {
+                        // points past the edge of the grid aren't in any cell
+                        let (x, y) = fluid.departure(field, j, i, dt);
+                        let (cx, cy) = (x as usize, y as usize);
+                        if x >= 0.0 && y >= 0.0 && fluid.inbounds(cx, cy) {
+                            let at = format!("{:?} {:?} face ({}, {})", backtrace, field, j, i);
+                            assert!(!fluid.element[cy][cx].is_static(), "{} traced to ({}, {})", at, x, y);
+                        }
+                        traced += 1;
+                    });
+                }
+            }
+            assert!(traced > 0);
+        }
+    }
+
+    #[test]
+    fn higher_order_backtraces_follow_solid_body_rotation() {
+        // the whole box turns 0.3 radians a step about its centre. the field is linear so bilinear
+        // sampling is exact, leaving only the integrator's own error
+        let mut fluid: Fluid = closed_box(64, "");
+        let (centre, radians) = (32.0, 0.3f32);
+        let spin: f32 = radians * fluid.grid_size / fluid.delta_t;
+        set_velocity(&mut fluid, |x, y| (-spin * (y - centre), spin * (x - centre)));
+
+        // mean distance from the exact departure points of the faces well clear of the walls
+        let mut error = |backtrace: Backtrace| -> f32 {
+            fluid.backtrace = backtrace;
+            let (mut total, mut count) = (0.0, 0.0);
+            fluid.each_advected(Field::U, |j, i| {
+                let (px, py) = (j as f32 - centre, i as f32 + 0.5 - centre);
+                if px * px + py * py > 20.0 * 20.0 {
+                    return;
+                }
+                let (sin, cos) = (-radians).sin_cos();
+                let exact = (centre + px * cos - py * sin, centre + px * sin + py * cos);
+                let (x, y) = fluid.departure(Field::U, j, i, fluid.delta_t);
+                total += ((x - exact.0).powi(2) + (y - exact.1).powi(2)).sqrt();
+                count += 1.0;
+            });
+            total / count
+        };
+
+        let errors: Vec<f32> = BACKTRACES.iter().map(|backtrace| error(*backtrace)).collect();
+        assert!(errors[0] > 0.5, "euler is off by {}", errors[0]);
+        assert!(errors.windows(2).all(|pair| pair[1] < pair[0]), "{:?}", errors);
+        assert!(errors[3] < errors[0] * 2e-3, "rk4 {} vs euler {}", errors[3], errors[0]);
+    }
+}
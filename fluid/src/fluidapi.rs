@@ -1,8 +1,10 @@
+use crate::error;
 use crate::fluid;
 use crate::utils;
 
 use std::arch;
 
+use error::FluidError;
 use fluid::{Ele, Fluid};
 use utils::{get_directions, Vector};
 
@@ -65,42 +67,65 @@ impl<'a> Oo<'a> {
         }
     }
 
-    pub fn peek_velocity(&self, dx: isize, dy: isize) -> f32 {
-        let (nx, ny): (usize, usize) = self.index(dx, dy);
-        match (dx, dy) {
-            (1, 0) => self.fluid.u[self.y][nx],
-            (-1, 0) => self.fluid.u[self.y][self.x],
-            (0, 1) => self.fluid.v[ny][self.x],
-            (0, -1) => self.fluid.v[self.y][self.x],
-            _ => {
-                eprintln!("OOB Error checking neighbor");
-                std::process::exit(1);
-            }
+    /// returns the velocity on the face shared with the neighbour at (dx, dy). only for the solver's
+    /// own call sites, which always pass axis offsets from an in-bounds Oo - anything else panics.
+    /// everyone else goes through <try_peek_velocity()>
+    pub(crate) fn peek_velocity(&self, dx: isize, dy: isize) -> f32 {
+        match self.try_peek_velocity(dx, dy) {
+            Ok(velocity) => velocity,
+            Err(err) => panic!("{}", err),
         }
     }
 
     /// operates fundamentally in the same way as <peek_velocity()> but instead
     /// returns a mutable reference to the specific cell's velocity
-    pub fn peek_velocity_mut(&mut self, dx: isize, dy: isize) -> &mut f32 {
-        let (nx, ny): (usize, usize) = self.index(dx, dy);
+    pub(crate) fn peek_velocity_mut(&mut self, dx: isize, dy: isize) -> &mut f32 {
+        match self.try_peek_velocity_mut(dx, dy) {
+            Ok(velocity) => velocity,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// returns the velocity on the face shared with the neighbour at (dx, dy). errors on non-axis
+    /// offsets or if the Oo itself sits outside the grid
+    pub fn try_peek_velocity(&self, dx: isize, dy: isize) -> Result<f32, FluidError> {
+        self.check_here()?;
         match (dx, dy) {
-            (1, 0) => &mut self.fluid.u[self.y][nx],
-            (-1, 0) => &mut self.fluid.u[self.y][self.x],
-            (0, 1) => &mut self.fluid.v[ny][self.x],
-            (0, -1) => &mut self.fluid.v[self.y][self.x],
-            _ => {
-                eprintln!("OOB Error fetching neighbor velocity reference");
-                std::process::exit(37);
-            }
+            (1, 0) => Ok(self.fluid.u[self.y][self.x + 1]),
+            (-1, 0) => Ok(self.fluid.u[self.y][self.x]),
+            (0, 1) => Ok(self.fluid.v[self.y + 1][self.x]),
+            (0, -1) => Ok(self.fluid.v[self.y][self.x]),
+            _ => Err(FluidError::InvalidOffset { dx, dy }),
+        }
+    }
+
+    /// operates in the same way as <try_peek_velocity()> but returns a mutable reference to the face
+    pub fn try_peek_velocity_mut(&mut self, dx: isize, dy: isize) -> Result<&mut f32, FluidError> {
+        self.check_here()?;
+        match (dx, dy) {
+            (1, 0) => Ok(&mut self.fluid.u[self.y][self.x + 1]),
+            (-1, 0) => Ok(&mut self.fluid.u[self.y][self.x]),
+            (0, 1) => Ok(&mut self.fluid.v[self.y + 1][self.x]),
+            (0, -1) => Ok(&mut self.fluid.v[self.y][self.x]),
+            _ => Err(FluidError::InvalidOffset { dx, dy }),
+        }
+    }
+
+    fn check_here(&self) -> Result<(), FluidError> {
+        if self.fluid.inbounds(self.x, self.y) {
+            Ok(())
+        }
+        else {
+            Err(FluidError::OutOfBounds { x: self.x as isize, y: self.y as isize })
         }
     }
 
-    pub fn divergence_here(&self) -> f32 {
+    pub(crate) fn divergence_here(&self) -> f32 {
         self.peek_velocity(1, 0) - self.peek_velocity(-1, 0) + self.peek_velocity(0, 1)
             - self.peek_velocity(0, -1)
     }
 
-    pub fn modify_adjacent(&mut self, adjustment: f32) {
+    pub(crate) fn modify_adjacent(&mut self, adjustment: f32) {
         if self.peek_element_here(1, 0).is_fluid() {
             *self.peek_velocity_mut(1, 0) += adjustment;
         }
@@ -115,22 +140,25 @@ impl<'a> Oo<'a> {
         }
     }
 
-    pub fn set_velocity_polarized(&mut self, set_x: f32, set_y: f32) {
+    pub(crate) fn set_velocity_polarized(&mut self, set_x: f32, set_y: f32) {
         *self.peek_velocity_mut(1, 0) = set_x;
         *self.peek_velocity_mut(-1, 0) = set_x;
         *self.peek_velocity_mut(0, 1) = set_y;
         *self.peek_velocity_mut(0, -1) = set_y;
     }
 
-    pub fn set_velocity_zeros(&mut self) {
+    pub(crate) fn set_velocity_zeros(&mut self) {
         *self.peek_velocity_mut(1, 0) = 0.0;
         *self.peek_velocity_mut(-1, 0) = 0.0;
         *self.peek_velocity_mut(0, 1) = 0.0;
         *self.peek_velocity_mut(0, -1) = 0.0;
     }
 
-    pub fn set_velocity_matched(&mut self, dref_x: isize, dref_y: isize) {
-        let (rx, ry) = self.index(dref_x, dref_y);
+    /// copies the face velocities of the master cell at the relative offset onto this cell. errors
+    /// if either cell falls outside the grid, leaving this cell untouched
+    pub fn set_velocity_matched(&mut self, dref_x: isize, dref_y: isize) -> Result<(), FluidError> {
+        self.check_here()?;
+        let (rx, ry) = self.try_index(dref_x, dref_y)?;
         let v10: f32;
         let vn0: f32;
         let v01: f32;
@@ -147,6 +175,8 @@ impl<'a> Oo<'a> {
         *self.peek_velocity_mut(-1, 0) = vn0 * damping;
         *self.peek_velocity_mut(0, 1) = v01 * damping;
         *self.peek_velocity_mut(0, -1) = v0n * damping;
+
+        Ok(())
     }
 
     pub fn afflicted_area(&self) -> f32 {
@@ -159,12 +189,26 @@ impl<'a> Oo<'a> {
         sides
     }
 
+    /// converts relative coordinates into an absolute position in the fluid's grid, or reports why
+    /// there isn't one instead of wrapping negative results around
+    pub fn try_index(&self, dx: isize, dy: isize) -> Result<(usize, usize), FluidError> {
+        let nx: isize = self.x as isize + dx;
+        let ny: isize = self.y as isize + dy;
+        if nx < 0 || ny < 0 || !self.fluid.inbounds(nx as usize, ny as usize) {
+            return Err(FluidError::OutOfBounds { x: nx, y: ny });
+        }
+
+        Ok((nx as usize, ny as usize))
+    }
+
     /// simply converts relative coordinates into valid, absolute indicies in
     /// the fluid's grid. this function gets called a lot of times, so it is
-    /// converted into x86 to ensure it's quick quick
+    /// converted into x86 to ensure it's quick quick. no checks are made - negative
+    /// results wrap to huge values, which <inbounds()> then rejects. kept inside the
+    /// crate for that reason, <try_index()> is the public version
     #[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
     #[cfg(target_arch = "x86_64")]
-    pub fn index(&self, dx: isize, dy: isize) -> (usize, usize) {
+    pub(crate) fn index(&self, dx: isize, dy: isize) -> (usize, usize) {
         let nx: usize;
         let ny: usize;
 
@@ -194,9 +238,32 @@ impl<'a> Oo<'a> {
     }
 
     #[cfg(not(target_arch = "x86_64"))]
-    pub fn index(&self, dx: isize, dy: isize) -> (usize, usize) {
+    pub(crate) fn index(&self, dx: isize, dy: isize) -> (usize, usize) {
         let nx: usize = ((self.x as isize) + dx) as usize;
         let ny: usize = ((self.y as isize) + dy) as usize;
         (nx, ny)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sized, stepped_fluid};
+
+    #[test]
+    fn bad_lookups_are_errors_rather_than_panics() {
+        let mut fluid: Fluid = stepped_fluid(&sized(10, 8), 0);
+
+        let mut corner = Oo::construct(0, 0, &mut fluid);
+        assert!(corner.try_peek_velocity(1, 0).is_ok());
+        assert!(matches!(corner.try_peek_velocity(1, 1), Err(FluidError::InvalidOffset { dx: 1, dy: 1 })));
+        assert!(matches!(corner.try_peek_velocity_mut(0, 2), Err(FluidError::InvalidOffset { dx: 0, dy: 2 })));
+        assert!(matches!(corner.try_index(-1, 0), Err(FluidError::OutOfBounds { x: -1, y: 0 })));
+        assert!(corner.set_velocity_matched(0, -1).is_err());
+
+        let mut outside = Oo::construct(10, 3, &mut fluid);
+        assert!(matches!(outside.try_peek_velocity(-1, 0), Err(FluidError::OutOfBounds { x: 10, y: 3 })));
+        assert!(outside.try_peek_velocity_mut(1, 0).is_err());
+        assert!(outside.set_velocity_matched(-1, 0).is_err());
+    }
+}
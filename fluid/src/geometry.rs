@@ -0,0 +1,45 @@
+//! closed polygons for obstacles that aren't circles or rectangles
+//!
+//! points are in grid cells with y pointing down, same as the rest of the solver. transforms return a
+//! new polygon so a shape can be built once and placed several times
+
+use crate::utils;
+
+use utils::Vector;
+
+/// closed outline, the last point joins back up with the first
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Polygon {
+    pub points: Vec<Vector<f32>>,
+}
+
+impl Polygon {
+    pub fn construct(points: Vec<Vector<f32>>) -> Polygon {
+        Polygon { points }
+    }
+
+    pub fn translated(&self, dx: f32, dy: f32) -> Polygon {
+        let points = self.points.iter().map(|point| Vector::construct(point.x + dx, point.y + dy)).collect();
+        Polygon { points }
+    }
+
+    /// rotates about <pivot> by <degrees>. positive angles turn clockwise on screen, as y points down
+    pub fn rotated(&self, degrees: f32, pivot: Vector<f32>) -> Polygon {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let points = self
+            .points
+            .iter()
+            .map(|point| {
+                let (dx, dy) = (point.x - pivot.x, point.y - pivot.y);
+                Vector::construct(pivot.x + dx * cos - dy * sin, pivot.y + dx * sin + dy * cos)
+            })
+            .collect();
+        Polygon { points }
+    }
+
+    /// scales about the origin, separately on each axis so a negative <sy> mirrors the shape vertically
+    pub fn scaled(&self, sx: f32, sy: f32) -> Polygon {
+        let points = self.points.iter().map(|point| Vector::construct(point.x * sx, point.y * sy)).collect();
+        Polygon { points }
+    }
+}
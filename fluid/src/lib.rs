@@ -0,0 +1,43 @@
+//! headless library interface to the fluid solver
+//!
+//! everything needed to construct, step and query a simulation lives here. the drawing code is
+//! only compiled in with the <gui> feature so tools and tests can embed the solver without
+//! linking macroquad or opening a window
+
+pub mod airfoil;
+pub mod batch;
+pub mod checkpoint;
+pub mod clone;
+pub mod config;
+pub mod error;
+pub mod export;
+pub mod field;
+pub mod fluid;
+pub mod fluidapi;
+pub mod geometry;
+pub mod mask;
+pub mod presets;
+pub mod pressure;
+pub mod render;
+pub mod scene;
+pub mod source;
+pub mod utils;
+
+mod legacy;
+mod units;
+
+#[cfg(test)]
+mod testing;
+
+pub use airfoil::{Airfoil, Naca};
+pub use clone::Clone;
+pub use config::Config;
+pub use error::FluidError;
+pub use field::Field2D;
+pub use fluid::{Ele, Field, Fluid};
+pub use fluidapi::Oo;
+pub use geometry::Polygon;
+pub use pressure::PressureSolver;
+pub use scene::Scene;
+pub use source::Source;
+pub use utils::Vector;
@@ -1,35 +1,122 @@
-mod clone;
-mod config;
-mod fluid;
-mod fluidapi;
-mod legacy;
-mod source;
-mod units;
-mod utils;
-
+#[cfg(feature = "gui")]
 use macroquad::prelude::*;
-use std::{env, time::Duration};
-
-use config::{configuration, Config, State, VisualMode};
-use fluid::Fluid;
-use utils::{place_tool, Vector};
-
-#[macroquad::main(configuration)]
-async fn main() {
+#[cfg(feature = "gui")]
+use std::time::Duration;
+use std::{env, process};
+
+use fluid::batch::{self, BatchOptions};
+#[cfg(feature = "gui")]
+use fluid::config::{configuration, State, VisualMode};
+use fluid::config::{Config, KEYS};
+#[cfg(feature = "gui")]
+use fluid::fluid::Fluid;
+#[cfg(feature = "gui")]
+use fluid::presets::{self, Preset, PRESETS};
+#[cfg(feature = "gui")]
+use fluid::render::{FieldView, Renderer, Rgba, WindowRenderer};
+#[cfg(feature = "gui")]
+use fluid::utils::{place_tool, Vector};
+
+/// where S saves and L loads drawn geometry, relative to the working directory
+#[cfg(feature = "gui")]
+const GEOMETRY_PATH: &str = "geometry.scene";
+
+fn main() {
     // used for debugging and backtracing panics
     env::set_var("RUST_BACKTRACE", "full");
     env::set_var("CARGO_PROFILE_RELEASE_DEBUG", "true");
 
-    let config: Config = Config::new();
-    let mut fluid: Fluid = Fluid::construct(&config);
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "run") && args.iter().any(|arg| arg == "--headless") {
+        process::exit(headless(args.into_iter().skip(1)));
+    }
+
+    // settings come from an optional --config file with any other --flag overriding it, so the
+    // window can be sized from the grid that is actually going to be simulated
+    let args = args.into_iter().skip_while(|arg| arg == "run");
+    let config: Config = match Config::from_args(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            usage();
+            process::exit(2);
+        }
+    };
+
+    interactive(config);
+}
+
+fn usage() {
+    eprintln!("usage: fluid [run] [--config FILE] [--{} VALUE]...", KEYS.join(" VALUE] [--"));
+    eprintln!(
+        "       fluid run --headless (--steps N | --time SECONDS) [--out DIR] [--every N | --interval SECONDS] \\"
+    );
+    eprintln!("                 [--output summary,checkpoint,gradient,vector,streamline,pressure,vti,vtk,npy,npz] \\");
+    eprintln!("                 [--resume CHECKPOINT] [--frame_scale PIXELS_PER_CELL] [--frame_speed SPEED] \\");
+    eprintln!("                 [config flags]...");
+}
+
+/// batch run with no window. exit status is 0 on success, 1 if the run failed part way through and
+/// 2 if the arguments were bad
+fn headless<I: IntoIterator<Item = String>>(args: I) -> i32 {
+    let (options, config) = match BatchOptions::from_args(args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            usage();
+            return 2;
+        }
+    };
+
+    match batch::run(&options, &config) {
+        Ok(report) => {
+            println!("finished {} steps ({:.3}s simulated)", report.steps, report.time);
+            for file in report.files {
+                println!("wrote {}", file.display());
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "gui"))]
+fn interactive(_config: Config) {
+    eprintln!("error: built without the gui feature, only headless runs are available");
+    usage();
+    process::exit(2);
+}
+
+#[cfg(feature = "gui")]
+fn interactive(config: Config) {
+    // the fluid is built before the window opens so a bad scene fails fast with a message
+    let fluid: Fluid = match Fluid::from_config(&config) {
+        Ok(fluid) => fluid,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(2);
+        }
+    };
+    // cycling starts from whichever preset is loaded, or the top of the list for a scene file
+    let preset: &'static Preset =
+        config.preset.as_deref().and_then(|name| presets::find(name).ok()).unwrap_or(&PRESETS[0]);
+
+    macroquad::Window::from_config(configuration(&config), simulate(fluid, preset));
+}
+
+#[cfg(feature = "gui")]
+async fn simulate(mut fluid: Fluid, mut preset: &'static Preset) {
     let mut state: State = State::new();
     let mut display: VisualMode = VisualMode::new();
+    let mut renderer: WindowRenderer = WindowRenderer;
     let mut p_mouse: Option<Vector<f32>> = None;
     let mut diag: bool = false;
 
     println!("Grid Size: {}", fluid.x * fluid.y);
 
-    fluid.assert_boundary_conditions();
     // runs some additional iterations thru the projection phase of the grid-solver.
     // this is used to just sort of "get some slack" out of the matrix, as the startup
     // phase takes the longest to converge in most cases
@@ -39,27 +126,11 @@ async fn main() {
 
     // starts loop for update-draw cycle
     while !is_key_pressed(KeyCode::Escape) {
-        clear_background(Color::from_hex(0x121212));
+        renderer.clear(Rgba::from_hex(0x121212));
 
         // visual enum used to select different visuals. blank mode is used to remove draw-loop overhead
         // and to allow faster iterations for long running sims
-        match display {
-            VisualMode::Gradient => {
-                fluid.display(true, false, false, 0.4, 0.7, 1, false, true);
-            }
-            VisualMode::Vector => {
-                fluid.display(true, true, false, 0.4, 0.7, 1, true, false);
-            }
-            VisualMode::Other => {
-                fluid.display(true, false, false, 0.4, 0.7, 1, false, true);
-                fluid.streamline(30, 10, 100, 0.07, 0.2);
-            }
-            VisualMode::Streamline => {
-                fluid.display(false, false, false, 1.0, 10.0, 1000, false, true);
-                fluid.streamline(30, 8, 155, 0.05, 0.2);
-            }
-            VisualMode::Blank => {}
-        }
+        renderer.visual(&FieldView::construct(&fluid), &display);
         if is_key_pressed(KeyCode::V) {
             display = display.rotate();
         }
@@ -111,12 +182,40 @@ async fn main() {
             fluid.reset();
         }
 
+        // keeps hand-drawn walls across sessions - reload with L, or later with --scene geometry.scene
+        if is_key_pressed(KeyCode::S) {
+            match fluid.save_geometry(GEOMETRY_PATH) {
+                Ok(()) => println!("saved geometry to {}", GEOMETRY_PATH),
+                Err(err) => eprintln!("error: {}", err),
+            }
+        }
+        else if is_key_pressed(KeyCode::L) {
+            match fluid.load_geometry(GEOMETRY_PATH) {
+                Ok(()) => println!("loaded geometry from {}", GEOMETRY_PATH),
+                Err(err) => eprintln!("error: {}", err),
+            }
+        }
+
+        // cycles through the built-in presets, rebuilding geometry and flow from scratch
+        if is_key_pressed(KeyCode::N) {
+            preset = presets::next(preset.name);
+            match fluid.load_preset(preset) {
+                Ok(()) => println!("preset: {} - {}", preset.name, preset.description),
+                Err(err) => eprintln!("error: {}", err),
+            }
+        }
+
         if is_key_pressed(KeyCode::K) {
             diag = !diag;
         }
         if diag {
             draw_text(&format!("FPS: {}", get_fps()), 30.0, 20.0, 20.0, RED);
             draw_text(&format!("b.c. len: {}", fluid.boundaries_dep.len()), 30.0, 40.0, 20.0, RED);
+            draw_text(&format!("preset: {}", preset.name), 30.0, 60.0, 20.0, RED);
+            let solve = &fluid.solve;
+            let solve = format!("solve: {} iters, {} {:.3}", solve.iterations, fluid.residual.name(), solve.residual);
+            draw_text(&solve, 30.0, 80.0, 20.0, RED);
+            draw_text(&format!("dt: {:.4}, t: {:.2}", fluid.dt, fluid.time), 30.0, 100.0, 20.0, RED);
         }
 
         // awaits next frame, optional delay but usually set to 0 as the sims run slow anyway
@@ -0,0 +1,346 @@
+//! obstacle geometry from bitmap images
+//!
+//! dark pixels become Static cells and, optionally, exact colours can be mapped to any other cell
+//! kind - e.g. paint the inlet red and the outlet green in an image editor. png, pbm, pgm and ppm
+//! (both ascii and binary netpbm flavours) are read. the image is stretched over the whole grid, so
+//! it should have the same aspect ratio as the simulation
+
+use crate::error;
+use crate::scene;
+
+use std::{fs, io::BufWriter, path::Path};
+
+use error::FluidError;
+use scene::CellKind;
+
+/// decoded image, always expanded to 8-bit rgba
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/// how a mask's pixels turn into cells
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaskRules {
+    /// cells whose average luminance is below this become Static
+    pub threshold: u8,
+
+    /// exact 0xRRGGBB colours mapped to a cell kind. checked before the luminance threshold
+    pub colours: Vec<(u32, CellKind)>,
+}
+
+impl Default for MaskRules {
+    fn default() -> MaskRules {
+        MaskRules { threshold: 128, colours: Vec::new() }
+    }
+}
+
+impl Mask {
+    pub fn construct(width: usize, height: usize, fill: [u8; 4]) -> Mask {
+        Mask { width, height, pixels: vec![fill; width * height] }
+    }
+
+    /// reads an image, picking the decoder from the file's magic bytes rather than its extension
+    pub fn from_file(path: &str) -> Result<Mask, FluidError> {
+        let bytes: Vec<u8> = fs::read(path).map_err(|err| io_error(path, err.to_string()))?;
+
+        if bytes.starts_with(b"\x89PNG") {
+            decode_png(&bytes).map_err(|reason| io_error(path, reason))
+        }
+        else if bytes.len() > 1 && bytes[0] == b'P' && (b'1'..=b'6').contains(&bytes[1]) {
+            decode_netpbm(&bytes).map_err(|reason| io_error(path, reason))
+        }
+        else {
+            Err(io_error(path, String::from("not a png, pbm, pgm or ppm image")))
+        }
+    }
+
+    /// resolves a mask path written in a scene file, relative to the scene's own directory
+    pub fn from_scene_path(scene_path: &str, path: &str) -> Result<Mask, FluidError> {
+        let base = Path::new(scene_path).parent().unwrap_or(Path::new(""));
+        Mask::from_file(&base.join(path).to_string_lossy())
+    }
+
+    /// writes the mask out as an 8-bit rgba png
+    pub fn write_png(&self, path: &str) -> Result<(), FluidError> {
+        let file = fs::File::create(path).map_err(|err| io_error(path, err.to_string()))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(|err| io_error(path, err.to_string()))?;
+        writer.write_image_data(self.pixels.concat().as_slice()).map_err(|err| io_error(path, err.to_string()))
+    }
+
+    /// colour under the centre of cell (x, y) of a width x height grid
+    pub fn sample_centre(&self, x: usize, y: usize, width: usize, height: usize) -> [u8; 4] {
+        let px = ((x as f32 + 0.5) * self.width as f32 / width as f32) as usize;
+        let py = ((y as f32 + 0.5) * self.height as f32 / height as f32) as usize;
+        self.pixels[py.min(self.height - 1) * self.width + px.min(self.width - 1)]
+    }
+
+    /// average luminance over every pixel cell (x, y) covers, treating transparent pixels as white.
+    /// averaging rather than point sampling keeps thin features from vanishing when scaling down
+    pub fn coverage_luma(&self, x: usize, y: usize, width: usize, height: usize) -> f32 {
+        let span = |cell: usize, cells: usize, pixels: usize| {
+            let lo = cell * pixels / cells;
+            let hi = ((cell + 1) * pixels).div_ceil(cells).max(lo + 1).min(pixels);
+            lo..hi
+        };
+
+        let (mut total, mut count) = (0.0, 0.0);
+        for py in span(y, height, self.height) {
+            for px in span(x, width, self.width) {
+                let [r, g, b, a] = self.pixels[py * self.width + px];
+                let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+                let alpha = a as f32 / 255.0;
+                total += luma * alpha + 255.0 * (1.0 - alpha);
+                count += 1.0;
+            }
+        }
+        total / count
+    }
+
+    /// cell kind for cell (x, y) of a width x height grid, or None where the mask leaves the fluid alone
+    pub fn classify(&self, rules: &MaskRules, x: usize, y: usize, width: usize, height: usize) -> Option<CellKind> {
+        let [r, g, b, a] = self.sample_centre(x, y, width, height);
+        let rgb = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        if a >= 128 {
+            if let Some((_, kind)) = rules.colours.iter().find(|(colour, _)| *colour == rgb) {
+                return Some(*kind);
+            }
+        }
+
+        if self.coverage_luma(x, y, width, height) < rules.threshold as f32 {
+            Some(CellKind::Static)
+        }
+        else {
+            None
+        }
+    }
+}
+
+fn io_error(path: &str, reason: String) -> FluidError {
+    FluidError::Io { path: path.to_string(), reason }
+}
+
+fn decode_png(bytes: &[u8]) -> Result<Mask, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|err| err.to_string())?;
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let channels = info.color_type.samples();
+    let pixels = buffer[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|px| match px {
+            [l] => [*l, *l, *l, 255],
+            [l, a] => [*l, *l, *l, *a],
+            [r, g, b] => [*r, *g, *b, 255],
+            [r, g, b, a] => [*r, *g, *b, *a],
+            _ => [255; 4],
+        })
+        .collect();
+
+    Ok(Mask { width, height, pixels })
+}
+
+/// reads any of the six netpbm formats. pbm uses 1 for black, the others are scaled by their maxval
+fn decode_netpbm(bytes: &[u8]) -> Result<Mask, String> {
+    let kind = bytes[1];
+    let mut pos = 2;
+
+    // header fields are whitespace separated and may have #comments in between
+    let header = |pos: &mut usize| -> Result<usize, String> {
+        loop {
+            while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+                *pos += 1;
+            }
+            if *pos < bytes.len() && bytes[*pos] == b'#' {
+                while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                    *pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        let start = *pos;
+        while *pos < bytes.len() && bytes[*pos].is_ascii_digit() {
+            *pos += 1;
+        }
+        std::str::from_utf8(&bytes[start..*pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(|| String::from("malformed netpbm header"))
+    };
+
+    let width = header(&mut pos)?;
+    let height = header(&mut pos)?;
+    let maxval = if matches!(kind, b'1' | b'4') { 1 } else { header(&mut pos)? };
+    if width == 0 || height == 0 || maxval == 0 || maxval > 65535 {
+        return Err(String::from("netpbm image has a zero size or bad maxval"));
+    }
+
+    let channels = if matches!(kind, b'3' | b'6') { 3 } else { 1 };
+    let binary = matches!(kind, b'4' | b'5' | b'6');
+
+    // binary data starts after a single whitespace following the header
+    let data = &bytes[(pos + binary as usize).min(bytes.len())..];
+    let row_bytes = width.div_ceil(8);
+
+    // the sizes come straight from the header, so a corrupt one mustn't be allowed to wrap around or to reserve
+    // more samples than the file could possibly hold. every ascii sample takes at least one byte
+    let too_large = || format!("a {}x{} netpbm image is too large to load", width, height);
+    let count = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(channels)).ok_or_else(too_large)?;
+    let needed = match kind {
+        b'1' | b'2' | b'3' => count,
+        b'4' => row_bytes.checked_mul(height).ok_or_else(too_large)?,
+        _ => count.checked_mul(if maxval > 255 { 2 } else { 1 }).ok_or_else(too_large)?,
+    };
+    if needed > data.len() {
+        return Err(String::from("truncated netpbm data"));
+    }
+    let mut samples: Vec<usize> = Vec::with_capacity(count);
+
+    match kind {
+        b'1' => {
+            // ascii bits may be packed without separators
+            for byte in data {
+                if samples.len() == count {
+                    break;
+                }
+                match byte {
+                    b'0' => samples.push(0),
+                    b'1' => samples.push(1),
+                    _ => {}
+                }
+            }
+        }
+        b'2' | b'3' => {
+            while samples.len() < count {
+                samples.push(header(&mut pos)?);
+            }
+        }
+        b'4' => {
+            // rows are padded out to whole bytes
+            for y in 0..height {
+                for x in 0..width {
+                    let byte = data.get(y * row_bytes + x / 8).ok_or("truncated pbm data")?;
+                    samples.push(((byte >> (7 - x % 8)) & 1) as usize);
+                }
+            }
+        }
+        _ => {
+            let wide = maxval > 255;
+            for idx in 0..count {
+                let sample = if wide {
+                    let hi = *data.get(idx * 2).ok_or("truncated netpbm data")? as usize;
+                    let lo = *data.get(idx * 2 + 1).ok_or("truncated netpbm data")? as usize;
+                    hi << 8 | lo
+                }
+                else {
+                    *data.get(idx).ok_or("truncated netpbm data")? as usize
+                };
+                samples.push(sample);
+            }
+        }
+    }
+    if samples.len() < count {
+        return Err(String::from("truncated netpbm data"));
+    }
+
+    let scale = |sample: usize| (sample.min(maxval) * 255 / maxval) as u8;
+    let pixels = match kind {
+        b'1' | b'4' => samples.iter().map(|bit| if *bit == 1 { [0, 0, 0, 255] } else { [255; 4] }).collect(),
+        b'2' | b'5' => samples.iter().map(|l| [scale(*l), scale(*l), scale(*l), 255]).collect(),
+        _ => samples.chunks_exact(3).map(|rgb| [scale(rgb[0]), scale(rgb[1]), scale(rgb[2]), 255]).collect(),
+    };
+
+    Ok(Mask { width, height, pixels })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const WHITE: [u8; 4] = [255; 4];
+
+    /// the same 3x2 checkerboard in each netpbm format. 3 wide so the binary pbm rows need padding
+    fn checkerboards() -> Vec<Vec<u8>> {
+        let binary = |header: &str, data: &[u8]| [header.as_bytes(), data].concat();
+        vec![
+            b"P1\n3 2\n101\n010\n".to_vec(),
+            b"P2\n# a comment\n3 2\n255\n0 255 0\n255 0 255\n".to_vec(),
+            b"P3\n3 2\n15\n0 0 0  15 15 15  0 0 0\n15 15 15  0 0 0  15 15 15\n".to_vec(),
+            binary("P4\n3 2\n", &[0b1010_0000, 0b0100_0000]),
+            binary("P5\n3 2\n65535\n", &[0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255, 255]),
+            binary("P6\n3 2\n255\n", &[[0; 3], [255; 3], [0; 3], [255; 3], [0; 3], [255; 3]].concat()),
+        ]
+    }
+
+    #[test]
+    fn every_netpbm_format_decodes_to_the_same_pixels() {
+        let expected = vec![BLACK, WHITE, BLACK, WHITE, BLACK, WHITE];
+        for bytes in checkerboards() {
+            let mask = decode_netpbm(&bytes).unwrap_or_else(|err| panic!("P{}: {}", bytes[1] as char, err));
+            assert_eq!((mask.width, mask.height), (3, 2));
+            assert_eq!(mask.pixels, expected, "P{}", bytes[1] as char);
+        }
+    }
+
+    #[test]
+    fn truncated_netpbm_data_is_refused() {
+        let truncated: [&[u8]; 6] = [
+            b"P1\n3 2\n101\n01",
+            b"P2\n3 2\n255\n0 255 0\n255 0",
+            b"P3\n3 2\n255\n0 0 0 255 255 255 0 0 0\n255 255 255 0 0 0 255 255",
+            b"P4\n3 2\n\xa0",
+            b"P5\n3 2\n65535\n\0\0\xff\xff\0\0\xff\xff\0\0\xff",
+            b"P6\n3 2\n255\n\0\0\0\xff\xff\xff\0\0\0\xff\xff\xff\0\0\0\xff\xff",
+        ];
+        for bytes in truncated {
+            assert!(decode_netpbm(bytes).is_err(), "P{} was accepted", bytes[1] as char);
+        }
+    }
+
+    #[test]
+    fn oversized_headers_are_refused_before_allocating() {
+        let huge = usize::MAX / 2 + 1;
+        for header in [
+            format!("P6\n{} 2\n255\n", huge),
+            format!("P4\n{} {}\n", huge, huge),
+            format!("P2\n{} 3\n255\n0 0 0", huge),
+            String::from("P5\n100000 100000\n65535\n"),
+            String::from("P1\n100000 100000\n0 1 0 1"),
+        ] {
+            let bytes = [header.as_bytes(), &[0; 64]].concat();
+            assert!(decode_netpbm(&bytes).is_err(), "{:?} was accepted", header);
+        }
+    }
+
+    #[test]
+    fn classify_applies_colours_before_the_threshold() {
+        // dark grey, light grey, opaque red and see-through red
+        let mut mask = Mask::construct(4, 1, WHITE);
+        mask.pixels = vec![[100, 100, 100, 255], [200, 200, 200, 255], [255, 0, 0, 255], [255, 0, 0, 0]];
+
+        let mut rules = MaskRules::default();
+        let kinds = |rules: &MaskRules| -> Vec<Option<CellKind>> {
+            (0..4).map(|x| mask.classify(rules, x, 0, 4, 1)).collect()
+        };
+        // red's luminance is about 76, so it falls under the default threshold
+        assert_eq!(kinds(&rules), [Some(CellKind::Static), None, Some(CellKind::Static), None]);
+
+        rules.threshold = 250;
+        assert_eq!(kinds(&rules), [Some(CellKind::Static), Some(CellKind::Static), Some(CellKind::Static), None]);
+
+        rules.threshold = 50;
+        rules.colours.push((0xff0000, CellKind::Source(None)));
+        assert_eq!(kinds(&rules), [None, None, Some(CellKind::Source(None)), None]);
+    }
+}
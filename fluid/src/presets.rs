@@ -0,0 +1,77 @@
+//! built-in scenes matching the examples in the readme
+//!
+//! each preset is one of the files in <scenes/>, compiled into the binary so it's always available.
+//! the <default> preset is the layout hardcoded in <Fluid::assert_boundary_conditions()>
+
+use crate::error;
+use crate::scene;
+
+use error::FluidError;
+use scene::Scene;
+
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    source: Option<&'static str>,
+}
+
+impl Preset {
+    /// the preset's scene, or None for the built-in layout
+    pub fn scene(&self) -> Option<Scene> {
+        self.source.map(|text| {
+            Scene::parse(&format!("preset '{}'", self.name), text)
+                .unwrap_or_else(|err| panic!("built-in preset failed to parse: {}", err))
+        })
+    }
+}
+
+pub const PRESETS: [Preset; 6] = [
+    Preset {
+        name: "default",
+        description: "jet through a slotted wall, the original hardcoded layout",
+        source: None,
+    },
+    Preset {
+        name: "cylinder",
+        description: "cylinder karman street between two walls",
+        source: Some(include_str!("../scenes/cylinder.scene")),
+    },
+    Preset {
+        name: "sphere",
+        description: "small bluff body karman street in open flow",
+        source: Some(include_str!("../scenes/sphere.scene")),
+    },
+    Preset {
+        name: "box",
+        description: "flow over a box sitting on an impenetrable floor",
+        source: Some(include_str!("../scenes/box.scene")),
+    },
+    Preset {
+        name: "gottingen",
+        description: "gottingen 1919 style airfoil at 5 degrees",
+        source: Some(include_str!("../scenes/gottingen.scene")),
+    },
+    Preset {
+        name: "puff",
+        description: "cylinder hit by a narrow jet of air",
+        source: Some(include_str!("../scenes/puff.scene")),
+    },
+];
+
+/// looks a preset up by name
+pub fn find(name: &str) -> Result<&'static Preset, FluidError> {
+    PRESETS.iter().find(|preset| preset.name == name).ok_or_else(|| FluidError::InvalidConfig {
+        key: String::from("preset"),
+        reason: format!(
+            "unknown preset '{}', expected one of: {}",
+            name,
+            PRESETS.iter().map(|preset| preset.name).collect::<Vec<_>>().join(", ")
+        ),
+    })
+}
+
+/// the preset after <name> in the registry, wrapping around - used to cycle through them at runtime
+pub fn next(name: &str) -> &'static Preset {
+    let idx = PRESETS.iter().position(|preset| preset.name == name).map_or(0, |idx| idx + 1);
+    &PRESETS[idx % PRESETS.len()]
+}
@@ -0,0 +1,51 @@
+use crate::fluid;
+use crate::fluidapi;
+
+use super::{PressureSolver, SolveReport};
+use fluid::{Ele, Fluid};
+use fluidapi::Oo;
+
+/// successive over-relaxation in place, one cell at a time with the fluid's <overrelaxation>. cheap
+/// per sweep but slow to shift large scale errors on big grids
+pub struct GaussSeidel;
+
+impl GaussSeidel {
+    fn sweep(fluid: &mut Fluid) {
+        let scale: f32 = fluid.pressure_scale();
+        for y in 0..fluid.y {
+            for x in 0..fluid.x {
+                if fluid.element[y][x] != Ele::Fluid {
+                    continue;
+                }
+
+                let mut oo: Oo = Oo::construct(x, y, fluid);
+
+                let divergence: f32 = oo.divergence_here();
+                let sides: f32 = oo.afflicted_area();
+
+                if sides == 0.0 {
+                    continue;
+                }
+
+                let correction: f32 = -divergence / sides * oo.fluid.overrelaxation;
+                oo.modify_adjacent(correction);
+                fluid.pressure[y][x] += correction * scale;
+            }
+        }
+    }
+}
+
+impl PressureSolver for GaussSeidel {
+    fn solve(&mut self, fluid: &mut Fluid) -> SolveReport {
+        // with no tolerance the residual is only measured once at the end, for the report
+        let converged = |fluid: &Fluid| fluid.tolerance > 0.0 && fluid.residual(fluid.residual) <= fluid.tolerance;
+
+        let mut iterations: usize = 0;
+        while iterations < fluid.iters && !converged(fluid) {
+            GaussSeidel::sweep(fluid);
+            iterations += 1;
+        }
+
+        SolveReport { iterations, residual: fluid.residual(fluid.residual) }
+    }
+}
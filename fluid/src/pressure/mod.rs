@@ -0,0 +1,85 @@
+//! pressure solves used by projection
+//!
+//! every solver works on the same system: one unknown correction per Fluid cell, chosen so that
+//! pushing it through the cell's open faces cancels the divergence. Static and Source faces are held
+//! by the boundary conditions so they drop out, and Clone cells count as open sides with no unknown
+//! of their own, which pins the solution the way an outflow would. warm starting and the pressure
+//! bookkeeping are done by <Fluid> before a solver is handed the grid, so solvers only ever add
+//! their own corrections on top
+
+pub mod gauss_seidel;
+pub mod multigrid;
+pub mod pcg;
+pub mod red_black;
+
+use crate::config;
+use crate::fluid;
+use crate::fluidapi;
+
+use config::ResidualNorm;
+use fluid::{Ele, Fluid};
+use fluidapi::Oo;
+
+pub use config::Coarsening;
+pub use gauss_seidel::GaussSeidel;
+pub use multigrid::Multigrid;
+pub use pcg::Pcg;
+pub use red_black::RedBlack;
+
+/// how a pressure solve went
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SolveReport {
+    /// iterations actually run, at most <iters>
+    pub iterations: usize,
+    /// divergence left over afterwards, measured with the fluid's <ResidualNorm>
+    pub residual: f32,
+}
+
+/// drives the fluid's velocities towards zero divergence. implementors stop at the fluid's
+/// <tolerance> or after <iters> iterations, whichever comes first, and add each correction they make
+/// to <pressure> scaled by <pressure_scale()>
+pub trait PressureSolver {
+    fn solve(&mut self, fluid: &mut Fluid) -> SolveReport;
+}
+
+/// true for the cells that carry an unknown - Fluid cells with at least one open side. a Fluid cell
+/// walled in on every side has nothing a correction could move
+fn is_unknown(fluid: &mut Fluid, x: usize, y: usize) -> bool {
+    fluid.element[y][x] == Ele::Fluid && Oo::construct(x, y, fluid).afflicted_area() > 0.0
+}
+
+/// pushes a finished solve's corrections through the faces and adds them to <pressure>
+fn apply_corrections(fluid: &mut Fluid, cells: &[(usize, usize)], corrections: &[f64]) {
+    let scale: f32 = fluid.pressure_scale();
+    for (&(x, y), &value) in cells.iter().zip(corrections) {
+        Oo::construct(x, y, fluid).modify_adjacent(value as f32);
+        fluid.pressure[y][x] += value as f32 * scale;
+    }
+}
+
+/// a solver's own residual vector is the divergence its corrections so far leave behind, so it's
+/// measured the same way <Fluid::residual()> would measure it on the grid
+fn norm(residual: &[f64], kind: ResidualNorm) -> f32 {
+    match kind {
+        ResidualNorm::Max => residual.iter().fold(0.0, |max: f64, value| max.max(value.abs())) as f32,
+        ResidualNorm::Rms if residual.is_empty() => 0.0,
+        ResidualNorm::Rms => {
+            let squares: f64 = residual.iter().map(|value| value * value).sum();
+            (squares / residual.len() as f64).sqrt() as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fluid::Fluid;
+    use crate::testing::{sized, stepped_fluid};
+
+    /// a small fluid that has just been advected, so there's real divergence for a solver to remove
+    pub(super) fn advected_fluid(width: usize, height: usize) -> Fluid {
+        let mut fluid: Fluid = stepped_fluid(&sized(width, height), 3);
+        fluid.update_fluid(false, true, true, false);
+        fluid.pressure.fill(0.0);
+        fluid
+    }
+}
@@ -0,0 +1,364 @@
+use crate::config;
+use crate::fluid;
+
+use super::{apply_corrections, is_unknown, norm, PressureSolver, SolveReport};
+use config::Coarsening;
+use fluid::{Ele, Fluid};
+
+/// red-black sweeps before and after each coarse correction
+const PRE_SWEEPS: usize = 2;
+const POST_SWEEPS: usize = 2;
+/// sweeps on the coarsest level, which is small enough for them to amount to an exact solve
+const COARSEST_SWEEPS: usize = 40;
+/// coarsening stops once neither side of a level is longer than this
+const COARSEST_SIDE: usize = 4;
+
+/// geometric multigrid. the first iteration is a full multigrid pass, building the solution up from
+/// the coarsest level, and every one after it a V-cycle. each cuts the residual by roughly the same
+/// factor whatever the grid size, so the cycles needed for a given tolerance stay flat as grids grow
+pub struct Multigrid {
+    pub coarsening: Coarsening,
+}
+
+impl Default for Multigrid {
+    fn default() -> Multigrid {
+        Multigrid::construct(Coarsening::new())
+    }
+}
+
+impl Multigrid {
+    pub fn construct(coarsening: Coarsening) -> Multigrid {
+        Multigrid { coarsening }
+    }
+}
+
+/// what a cell is to the pressure system on one level
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// carries an unknown
+    Unknown,
+    /// open, but with its correction held at zero - a Clone outflow on the finest level
+    Pinned,
+    /// closed - Static, Source or a Fluid cell with no open side
+    Solid,
+}
+
+/// one grid of the hierarchy. corrections at coarse levels are stored unscaled, the operator is the
+/// same open sides minus unknown neighbours stencil everywhere and restriction sums each block, which
+/// together account for the cells getting twice as wide per level
+struct Level {
+    width: usize,
+    height: usize,
+    kind: Vec<Kind>,
+    /// open sides of each unknown, the operator's diagonal
+    diagonal: Vec<f64>,
+    solution: Vec<f64>,
+    rhs: Vec<f64>,
+    residual: Vec<f64>,
+}
+
+impl Level {
+    fn construct(width: usize, height: usize, mut kind: Vec<Kind>) -> Level {
+        let mut diagonal: Vec<f64> = vec![0.0; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * width + x;
+                if kind[idx] != Kind::Unknown {
+                    continue;
+                }
+
+                let open = Level::neighbours(width, height, x, y).filter(|&near| kind[near] != Kind::Solid).count();
+                // a coarse block can end up sealed in by its neighbours, leaving nothing to solve for
+                if open == 0 {
+                    kind[idx] = Kind::Solid;
+                }
+                diagonal[idx] = open as f64;
+            }
+        }
+
+        let cells = width * height;
+        Level {
+            width,
+            height,
+            kind,
+            diagonal,
+            solution: vec![0.0; cells],
+            rhs: vec![0.0; cells],
+            residual: vec![0.0; cells],
+        }
+    }
+
+    /// in-grid neighbours of a cell as flat indices
+    fn neighbours(width: usize, height: usize, x: usize, y: usize) -> impl Iterator<Item = usize> {
+        [(-1, 0), (1, 0), (0, -1), (0, 1)].into_iter().filter_map(move |(dx, dy): (isize, isize)| {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height {
+                return None;
+            }
+            Some(ny as usize * width + nx as usize)
+        })
+    }
+
+    /// sum of the unknown neighbours' corrections. pinned ones are zero by definition
+    fn neighbour_sum(&self, x: usize, y: usize) -> f64 {
+        Level::neighbours(self.width, self.height, x, y)
+            .filter(|&near| self.kind[near] == Kind::Unknown)
+            .map(|near| self.solution[near])
+            .sum()
+    }
+
+    /// red-black gauss seidel, all cells of one colour and then the other
+    fn smooth(&mut self, sweeps: usize) {
+        for _ in 0..sweeps {
+            for colour in 0..2 {
+                for y in 0..self.height {
+                    for x in ((y + colour) % 2..self.width).step_by(2) {
+                        let idx = y * self.width + x;
+                        if self.kind[idx] != Kind::Unknown {
+                            continue;
+                        }
+                        self.solution[idx] = (self.rhs[idx] + self.neighbour_sum(x, y)) / self.diagonal[idx];
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_residual(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let idx = y * self.width + x;
+                self.residual[idx] = match self.kind[idx] {
+                    Kind::Unknown => {
+                        self.rhs[idx] - (self.diagonal[idx] * self.solution[idx] - self.neighbour_sum(x, y))
+                    }
+                    _ => 0.0,
+                };
+            }
+        }
+    }
+
+    fn coarsen(&self, rule: Coarsening) -> Level {
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let mut kind: Vec<Kind> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let block: Vec<Kind> = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .into_iter()
+                    .map(|(dx, dy)| (2 * x + dx, 2 * y + dy))
+                    .filter(|&(fx, fy)| fx < self.width && fy < self.height)
+                    .map(|(fx, fy)| self.kind[fy * self.width + fx])
+                    .collect();
+                let solid = block.iter().filter(|&&cell| cell == Kind::Solid).count();
+
+                let closed = match rule {
+                    Coarsening::Conservative => solid == block.len(),
+                    Coarsening::Majority => solid * 2 > block.len(),
+                };
+                // pinned cells win over unknowns so outflows don't get blurred away on coarse levels
+                kind.push(if closed {
+                    Kind::Solid
+                }
+                else if block.contains(&Kind::Pinned) {
+                    Kind::Pinned
+                }
+                else {
+                    Kind::Unknown
+                });
+            }
+        }
+        Level::construct(width, height, kind)
+    }
+
+    /// sums the fine level's <source> values over each block into this level's rhs
+    fn restrict(&mut self, fine: &Level, source: &[f64]) {
+        self.rhs.fill(0.0);
+        for y in 0..fine.height {
+            for x in 0..fine.width {
+                let idx = y * fine.width + x;
+                let coarse = (y / 2) * self.width + x / 2;
+                if fine.kind[idx] == Kind::Unknown && self.kind[coarse] == Kind::Unknown {
+                    self.rhs[coarse] += source[idx];
+                }
+            }
+        }
+    }
+
+    /// adds this level's solution onto the fine level's, interpolated bilinearly between the four
+    /// nearest coarse cells. solid cells are left out and the remaining weights renormalised
+    fn prolong(&self, fine: &mut Level) {
+        for y in 0..fine.height {
+            for x in 0..fine.width {
+                let idx = y * fine.width + x;
+                if fine.kind[idx] != Kind::Unknown {
+                    continue;
+                }
+
+                let (cx, cy) = ((x / 2) as isize, (y / 2) as isize);
+                let (ox, oy) = (if x % 2 == 0 { -1 } else { 1 }, if y % 2 == 0 { -1 } else { 1 });
+                let (mut sum, mut weights): (f64, f64) = (0.0, 0.0);
+                for (dx, dy, weight) in [(0, 0, 9.0), (ox, 0, 3.0), (0, oy, 3.0), (ox, oy, 1.0)] {
+                    let (nx, ny) = (cx + dx, cy + dy);
+                    if nx < 0 || ny < 0 || nx as usize >= self.width || ny as usize >= self.height {
+                        continue;
+                    }
+                    let coarse = ny as usize * self.width + nx as usize;
+                    if self.kind[coarse] != Kind::Solid {
+                        sum += weight * self.solution[coarse];
+                        weights += weight;
+                    }
+                }
+                if weights > 0.0 {
+                    fine.solution[idx] += sum / weights;
+                }
+            }
+        }
+    }
+}
+
+fn v_cycle(levels: &mut [Level]) {
+    let Some((fine, coarser)) = levels.split_first_mut()
+    else {
+        return;
+    };
+    if coarser.is_empty() {
+        fine.smooth(COARSEST_SWEEPS);
+        return;
+    }
+
+    fine.smooth(PRE_SWEEPS);
+    fine.update_residual();
+    coarser[0].restrict(fine, &fine.residual);
+    coarser[0].solution.fill(0.0);
+    v_cycle(coarser);
+    coarser[0].prolong(fine);
+    fine.smooth(POST_SWEEPS);
+}
+
+/// solves the restricted problem on the coarsest level and works back up, using each level's
+/// answer as the starting point for a V-cycle on the next
+fn full_multigrid(levels: &mut [Level]) {
+    for idx in 1..levels.len() {
+        let (fine, coarse) = levels.split_at_mut(idx);
+        let fine = &fine[idx - 1];
+        coarse[0].restrict(fine, &fine.rhs);
+    }
+    for level in levels.iter_mut() {
+        level.solution.fill(0.0);
+    }
+
+    for idx in (0..levels.len()).rev() {
+        if idx + 1 < levels.len() {
+            let (fine, coarse) = levels.split_at_mut(idx + 1);
+            coarse[0].prolong(&mut fine[idx]);
+        }
+        v_cycle(&mut levels[idx..]);
+    }
+}
+
+impl PressureSolver for Multigrid {
+    fn solve(&mut self, fluid: &mut Fluid) -> SolveReport {
+        let mut kind: Vec<Kind> = Vec::with_capacity(fluid.x * fluid.y);
+        let mut cells: Vec<(usize, usize)> = Vec::new();
+        for y in 0..fluid.y {
+            for x in 0..fluid.x {
+                if is_unknown(fluid, x, y) {
+                    kind.push(Kind::Unknown);
+                    cells.push((x, y));
+                }
+                else if let Ele::Clone(_) = fluid.element[y][x] {
+                    kind.push(Kind::Pinned);
+                }
+                else {
+                    kind.push(Kind::Solid);
+                }
+            }
+        }
+
+        let mut levels: Vec<Level> = vec![Level::construct(fluid.x, fluid.y, kind)];
+        while let Some(level) = levels.last().filter(|level| level.width.max(level.height) > COARSEST_SIDE) {
+            levels.push(level.coarsen(self.coarsening));
+        }
+
+        let width = fluid.x;
+        let finest: &mut Level = &mut levels[0];
+        for &(x, y) in &cells {
+            finest.rhs[y * width + x] = -fluid.divergence_at(x, y) as f64;
+        }
+        finest.residual.copy_from_slice(&finest.rhs);
+
+        let measure = |level: &Level| -> f32 {
+            let residual: Vec<f64> = cells.iter().map(|&(x, y)| level.residual[y * width + x]).collect();
+            norm(&residual, fluid.residual)
+        };
+
+        let mut iterations: usize = 0;
+        let mut previous: f32 = measure(&levels[0]);
+        while iterations < fluid.iters && !(fluid.tolerance > 0.0 && previous <= fluid.tolerance) {
+            if iterations == 0 {
+                full_multigrid(&mut levels);
+            }
+            else {
+                v_cycle(&mut levels);
+            }
+            iterations += 1;
+
+            levels[0].update_residual();
+            let current: f32 = measure(&levels[0]);
+            // down at rounding error, further cycles only shuffle it around
+            if current >= previous {
+                break;
+            }
+            previous = current;
+        }
+
+        let corrections: Vec<f64> = cells.iter().map(|&(x, y)| levels[0].solution[y * width + x]).collect();
+        apply_corrections(fluid, &cells, &corrections);
+
+        SolveReport { iterations, residual: fluid.residual(fluid.residual) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::advected_fluid;
+    use super::*;
+
+    #[test]
+    fn reaches_the_tolerance_with_either_coarsening() {
+        for coarsening in [Coarsening::Conservative, Coarsening::Majority] {
+            let mut fluid: Fluid = advected_fluid(48, 32);
+            assert!(fluid.residual(fluid.residual) > 1.0);
+            (fluid.iters, fluid.tolerance) = (100, 1e-2);
+
+            fluid.project_with(&mut Multigrid::construct(coarsening));
+            assert!(fluid.solve.iterations < fluid.iters);
+            assert!(fluid.solve.residual <= 1e-2, "{:?} residual {}", coarsening, fluid.solve.residual);
+        }
+    }
+    #[test]
+    fn cycle_count_barely_grows_with_the_grid() {
+        // 16 times the cells. plain relaxation would need about 16 times the sweeps to get as far
+        let cycles = |size: usize| -> Vec<usize> {
+            let mut fluid: Fluid = advected_fluid(size, size);
+            let (u, v) = (fluid.u.clone(), fluid.v.clone());
+            (fluid.iters, fluid.tolerance) = (100, 1e-3);
+
+            [Coarsening::Conservative, Coarsening::Majority]
+                .into_iter()
+                .map(|coarsening| {
+                    (fluid.u, fluid.v) = (u.clone(), v.clone());
+                    fluid.pressure.fill(0.0);
+                    fluid.project_with(&mut Multigrid::construct(coarsening));
+                    assert!(fluid.solve.residual <= 1e-3, "{:?} {}: {}", coarsening, size, fluid.solve.residual);
+                    fluid.solve.iterations
+                })
+                .collect()
+        };
+
+        let (small, large) = (cycles(64), cycles(256));
+        for (small, large) in small.into_iter().zip(large) {
+            assert!(large <= small + 4, "{} cycles at 64x64 but {} at 256x256", small, large);
+        }
+    }
+}
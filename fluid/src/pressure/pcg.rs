@@ -0,0 +1,207 @@
+use crate::fluid;
+use crate::fluidapi;
+
+use super::{apply_corrections, is_unknown, norm, PressureSolver, SolveReport};
+use fluid::Fluid;
+use fluidapi::Oo;
+
+/// marks a neighbour that isn't an unknown - a wall, source, clone or the edge of the grid
+const NONE: usize = usize::MAX;
+
+/// how much of the dropped fill-in MIC(0) puts back on the diagonal. 0 is plain incomplete cholesky
+const TUNING: f64 = 0.97;
+/// a diagonal that falls below this fraction of its original value is reset rather than trusted
+const SAFETY: f64 = 0.25;
+
+/// conjugate gradient preconditioned with modified incomplete cholesky, MIC(0). each iteration
+/// costs a few sweeps' worth of work but the count needed barely grows with the grid, so on large
+/// grids it gets far closer to divergence free than gauss seidel for the same time
+pub struct Pcg;
+
+/// the projection matrix in compressed form. unknowns are numbered in row-major order, which the
+/// preconditioner relies on - a cell's left and up neighbours always come before it
+struct System {
+    cells: Vec<(usize, usize)>,
+    /// open side count of each unknown, the matrix diagonal
+    diagonal: Vec<f64>,
+    /// left, right, up, down neighbours that are unknowns too, each coupled with a -1
+    neighbours: Vec<[usize; 4]>,
+}
+
+impl System {
+    fn construct(fluid: &mut Fluid) -> System {
+        let mut index: Vec<usize> = vec![NONE; fluid.x * fluid.y];
+        let mut cells: Vec<(usize, usize)> = Vec::new();
+        let mut diagonal: Vec<f64> = Vec::new();
+        for y in 0..fluid.y {
+            for x in 0..fluid.x {
+                if is_unknown(fluid, x, y) {
+                    index[y * fluid.x + x] = cells.len();
+                    cells.push((x, y));
+                    diagonal.push(Oo::construct(x, y, fluid).afflicted_area() as f64);
+                }
+            }
+        }
+
+        let lookup = |x: usize, y: usize, dx: isize, dy: isize| -> usize {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            if nx < 0 || ny < 0 || nx as usize >= fluid.x || ny as usize >= fluid.y {
+                return NONE;
+            }
+            index[ny as usize * fluid.x + nx as usize]
+        };
+        let neighbours: Vec<[usize; 4]> = cells
+            .iter()
+            .map(|&(x, y)| [lookup(x, y, -1, 0), lookup(x, y, 1, 0), lookup(x, y, 0, -1), lookup(x, y, 0, 1)])
+            .collect();
+
+        System { cells, diagonal, neighbours }
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn multiply(&self, vector: &[f64], out: &mut [f64]) {
+        for (idx, value) in out.iter_mut().enumerate() {
+            let mut sum: f64 = self.diagonal[idx] * vector[idx];
+            for neighbour in self.neighbours[idx] {
+                if neighbour != NONE {
+                    sum -= vector[neighbour];
+                }
+            }
+            *value = sum;
+        }
+    }
+
+    /// inverse square roots of the MIC(0) factor's diagonal
+    fn preconditioner(&self) -> Vec<f64> {
+        let mut precon: Vec<f64> = vec![0.0; self.len()];
+        for idx in 0..self.len() {
+            let [left, _, up, _] = self.neighbours[idx];
+            let mut pivot: f64 = self.diagonal[idx];
+            if left != NONE {
+                let square: f64 = precon[left] * precon[left];
+                pivot -= square;
+                if self.neighbours[left][3] != NONE {
+                    pivot -= TUNING * square;
+                }
+            }
+            if up != NONE {
+                let square: f64 = precon[up] * precon[up];
+                pivot -= square;
+                if self.neighbours[up][1] != NONE {
+                    pivot -= TUNING * square;
+                }
+            }
+            if pivot < SAFETY * self.diagonal[idx] {
+                pivot = self.diagonal[idx];
+            }
+            precon[idx] = 1.0 / pivot.sqrt();
+        }
+        precon
+    }
+
+    /// solves L L^T out = residual with the factor described by <precon>
+    fn apply_preconditioner(&self, precon: &[f64], residual: &[f64], scratch: &mut [f64], out: &mut [f64]) {
+        for idx in 0..self.len() {
+            let [left, _, up, _] = self.neighbours[idx];
+            let mut value: f64 = residual[idx];
+            if left != NONE {
+                value += precon[left] * scratch[left];
+            }
+            if up != NONE {
+                value += precon[up] * scratch[up];
+            }
+            scratch[idx] = value * precon[idx];
+        }
+        for idx in (0..self.len()).rev() {
+            let [_, right, _, down] = self.neighbours[idx];
+            let mut value: f64 = scratch[idx];
+            if right != NONE {
+                value += precon[idx] * out[right];
+            }
+            if down != NONE {
+                value += precon[idx] * out[down];
+            }
+            out[idx] = value * precon[idx];
+        }
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+impl PressureSolver for Pcg {
+    fn solve(&mut self, fluid: &mut Fluid) -> SolveReport {
+        let system: System = System::construct(fluid);
+        let cells: usize = system.len();
+
+        let mut correction: Vec<f64> = vec![0.0; cells];
+        let mut residual: Vec<f64> =
+            system.cells.iter().map(|&(x, y)| -fluid.divergence_at(x, y) as f64).collect();
+        let converged = |residual: &[f64]| fluid.tolerance > 0.0 && norm(residual, fluid.residual) <= fluid.tolerance;
+
+        let mut iterations: usize = 0;
+        if !converged(&residual) {
+            let precon: Vec<f64> = system.preconditioner();
+            let mut scratch: Vec<f64> = vec![0.0; cells];
+            let mut auxiliary: Vec<f64> = vec![0.0; cells];
+            system.apply_preconditioner(&precon, &residual, &mut scratch, &mut auxiliary);
+            let mut search: Vec<f64> = auxiliary.clone();
+            let mut sigma: f64 = dot(&auxiliary, &residual);
+
+            while iterations < fluid.iters && sigma > 0.0 {
+                system.multiply(&search, &mut auxiliary);
+                let curvature: f64 = dot(&search, &auxiliary);
+                // only a singular system, e.g. a closed box, gets here - there's nothing left to gain
+                if curvature <= 0.0 {
+                    break;
+                }
+
+                let alpha: f64 = sigma / curvature;
+                for idx in 0..cells {
+                    correction[idx] += alpha * search[idx];
+                    residual[idx] -= alpha * auxiliary[idx];
+                }
+                iterations += 1;
+                if converged(&residual) {
+                    break;
+                }
+
+                system.apply_preconditioner(&precon, &residual, &mut scratch, &mut auxiliary);
+                let next: f64 = dot(&auxiliary, &residual);
+                let beta: f64 = next / sigma;
+                for idx in 0..cells {
+                    search[idx] = auxiliary[idx] + beta * search[idx];
+                }
+                sigma = next;
+            }
+        }
+
+        apply_corrections(fluid, &system.cells, &correction);
+
+        SolveReport { iterations, residual: fluid.residual(fluid.residual) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::advected_fluid;
+    use super::*;
+    use crate::config::ResidualNorm;
+
+    #[test]
+    fn reaches_the_tolerance() {
+        for norm in [ResidualNorm::Max, ResidualNorm::Rms] {
+            let mut fluid: Fluid = advected_fluid(48, 32);
+            assert!(fluid.residual(norm) > 1.0);
+            (fluid.iters, fluid.tolerance, fluid.residual) = (1000, 1e-2, norm);
+
+            fluid.project_with(&mut Pcg);
+            assert!(fluid.solve.iterations < fluid.iters);
+            assert!(fluid.solve.residual <= 1e-2, "{:?} residual {}", norm, fluid.solve.residual);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use crate::config;
+use crate::fluid;
+use crate::fluidapi;
+
+use std::{
+    ops::Range,
+    sync::{Barrier, Mutex, RwLock},
+    thread,
+};
+
+use super::{PressureSolver, SolveReport};
+use config::ResidualNorm;
+use fluid::{Ele, Fluid};
+use fluidapi::Oo;
+
+/// successive over-relaxation in checkerboard order. every cell of one colour only touches faces
+/// shared with the other colour, so a whole colour is relaxed at once - split into bands of rows, one
+/// per thread - and the result is the same whatever the thread count. same <overrelaxation> and mask
+/// handling as <GaussSeidel>, though the different order means the two don't match bit for bit
+pub struct RedBlack {
+    /// worker threads, 0 for one per core
+    pub threads: usize,
+}
+
+impl Default for RedBlack {
+    fn default() -> RedBlack {
+        RedBlack::construct(0)
+    }
+}
+
+impl RedBlack {
+    pub fn construct(threads: usize) -> RedBlack {
+        RedBlack { threads }
+    }
+
+    fn thread_count(&self) -> usize {
+        match self.threads {
+            0 => thread::available_parallelism().map_or(1, |count| count.get()),
+            count => count,
+        }
+    }
+}
+
+/// splits a row-major grid into consecutive bands of rows. the last band also takes <extra> rows,
+/// for the one extra row of v faces below the grid
+fn split_rows<'a>(mut data: &'a mut [f32], row: usize, bounds: &[usize], extra: usize) -> Vec<&'a mut [f32]> {
+    let mut bands = Vec::with_capacity(bounds.len() - 1);
+    for (idx, pair) in bounds.windows(2).enumerate() {
+        let rows = pair[1] - pair[0] + if idx == bounds.len() - 2 { extra } else { 0 };
+        let (band, rest) = data.split_at_mut(rows * row);
+        bands.push(band);
+        data = rest;
+    }
+    bands
+}
+
+fn locked(bands: Vec<&mut [f32]>) -> Vec<RwLock<&mut [f32]>> {
+    bands.into_iter().map(RwLock::new).collect()
+}
+
+/// everything the workers share. each band's slices are written only by the worker that owns it,
+/// and neighbouring bands are only read across a barrier from those writes, so the locks never wait
+struct Shared<'a> {
+    width: usize,
+    height: usize,
+    bounds: Vec<usize>,
+    /// open sides of every cell that gets relaxed, 0 for the rest
+    sides: Vec<f32>,
+    /// whether a correction may push through a face into the cell - the Fluid and Clone cells
+    open: Vec<bool>,
+
+    u: Vec<RwLock<&'a mut [f32]>>,
+    v: Vec<RwLock<&'a mut [f32]>>,
+    correction: Vec<RwLock<&'a mut [f32]>>,
+    /// per row (largest divergence, sum of squares, cells) for the tolerance check
+    rows: Mutex<Vec<(f32, f64, usize)>>,
+    barrier: Barrier,
+
+    overrelaxation: f32,
+    scale: f32,
+    iters: usize,
+    tolerance: f32,
+    norm: ResidualNorm,
+}
+
+impl Shared<'_> {
+    fn is_open(&self, x: isize, y: isize) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width
+            && (y as usize) < self.height
+            && self.open[y as usize * self.width + x as usize]
+    }
+
+    /// divergence of every relaxed cell in the band's rows, straight off the faces
+    fn divergence(&self, band: usize, mut each: impl FnMut(usize, usize, f32)) {
+        let rows: Range<usize> = self.bounds[band]..self.bounds[band + 1];
+        let u = self.u[band].read().unwrap();
+        let v = self.v[band].read().unwrap();
+        // the row of v faces under the band belongs to the next one down
+        let below = (band + 1 < self.v.len()).then(|| self.v[band + 1].read().unwrap());
+
+        for y in rows.clone() {
+            let local = y - rows.start;
+            for x in 0..self.width {
+                if self.sides[y * self.width + x] == 0.0 {
+                    continue;
+                }
+                let (left, right) = (u[local * (self.width + 1) + x], u[local * (self.width + 1) + x + 1]);
+                let top = v[local * self.width + x];
+                let bottom = match &below {
+                    Some(below) if y + 1 == rows.end => below[x],
+                    _ => v[(local + 1) * self.width + x],
+                };
+                each(x, y, right - left + bottom - top);
+            }
+        }
+    }
+
+    /// corrections for the cells of one colour in the band, zero everywhere else
+    fn relax(&self, band: usize, colour: usize, pressure: &mut [f32]) {
+        let start = self.bounds[band];
+        let mut values: Vec<(usize, f32)> = Vec::new();
+        self.divergence(band, |x, y, divergence| {
+            if (x + y) % 2 == colour {
+                let correction: f32 = -divergence / self.sides[y * self.width + x] * self.overrelaxation;
+                values.push(((y - start) * self.width + x, correction));
+            }
+        });
+
+        let mut correction = self.correction[band].write().unwrap();
+        correction.fill(0.0);
+        for (idx, value) in values {
+            correction[idx] = value;
+            pressure[idx] += value * self.scale;
+        }
+    }
+
+    /// pushes the corrections through the faces of the band's rows. every face gets the correction
+    /// of whichever neighbour was relaxed, provided the cell on the other side is open
+    fn apply(&self, band: usize) {
+        let rows: Range<usize> = self.bounds[band]..self.bounds[band + 1];
+        let width = self.width;
+        let correction = self.correction[band].read().unwrap();
+        // the cells above the band's first row of v faces belong to the band above
+        let above = (band > 0).then(|| self.correction[band - 1].read().unwrap());
+        let corrected = |x: usize, y: usize| -> f32 {
+            if y >= rows.start {
+                correction[(y - rows.start) * width + x]
+            }
+            else {
+                above.as_ref().map_or(0.0, |above| above[above.len() - width + x])
+            }
+        };
+
+        let mut u = self.u[band].write().unwrap();
+        for y in rows.clone() {
+            for x in 0..=width {
+                let mut delta: f32 = 0.0;
+                if x > 0 && self.is_open(x as isize, y as isize) {
+                    delta += corrected(x - 1, y);
+                }
+                if x < width && self.is_open(x as isize - 1, y as isize) {
+                    delta -= corrected(x, y);
+                }
+                u[(y - rows.start) * (width + 1) + x] += delta;
+            }
+        }
+
+        let mut v = self.v[band].write().unwrap();
+        let end = if rows.end == self.height { rows.end + 1 } else { rows.end };
+        for y in rows.start..end {
+            for x in 0..width {
+                let mut delta: f32 = 0.0;
+                if y > 0 && self.is_open(x as isize, y as isize) {
+                    delta += corrected(x, y - 1);
+                }
+                if y < self.height && self.is_open(x as isize, y as isize - 1) {
+                    delta -= corrected(x, y);
+                }
+                v[(y - rows.start) * width + x] += delta;
+            }
+        }
+    }
+
+    /// residual over the whole grid, reduced row by row in order so every worker - and every thread
+    /// count - arrives at exactly the same value
+    fn residual(&self, band: usize) -> f32 {
+        let mut rows: Vec<(f32, f64, usize)> = vec![(0.0, 0.0, 0); self.bounds[band + 1] - self.bounds[band]];
+        let start = self.bounds[band];
+        self.divergence(band, |_, y, divergence| {
+            let row = &mut rows[y - start];
+            row.0 = row.0.max(divergence.abs());
+            row.1 += (divergence as f64).powi(2);
+            row.2 += 1;
+        });
+        self.rows.lock().unwrap()[start..start + rows.len()].copy_from_slice(&rows);
+        self.barrier.wait();
+
+        let (max, squares, cells) = self
+            .rows
+            .lock()
+            .unwrap()
+            .iter()
+            .fold((0.0, 0.0, 0), |(max, squares, cells): (f32, f64, usize), row| {
+                (max.max(row.0), squares + row.1, cells + row.2)
+            });
+        match self.norm {
+            ResidualNorm::Max => max,
+            ResidualNorm::Rms if cells == 0 => 0.0,
+            ResidualNorm::Rms => (squares / cells as f64).sqrt() as f32,
+        }
+    }
+
+    /// one worker's share of the whole solve, returning the iterations run
+    fn work(&self, band: usize, pressure: &mut [f32]) -> usize {
+        let mut iterations: usize = 0;
+        loop {
+            if self.tolerance > 0.0 {
+                let residual: f32 = self.residual(band);
+                // nobody may start overwriting the row table until everyone has read it
+                self.barrier.wait();
+                if residual <= self.tolerance {
+                    break;
+                }
+            }
+            if iterations == self.iters {
+                break;
+            }
+
+            for colour in 0..2 {
+                self.relax(band, colour, pressure);
+                self.barrier.wait();
+                self.apply(band);
+                self.barrier.wait();
+            }
+            iterations += 1;
+        }
+        iterations
+    }
+}
+
+impl PressureSolver for RedBlack {
+    fn solve(&mut self, fluid: &mut Fluid) -> SolveReport {
+        let (width, height) = (fluid.x, fluid.y);
+        let mut sides: Vec<f32> = vec![0.0; width * height];
+        let mut open: Vec<bool> = vec![false; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                open[y * width + x] = fluid.element[y][x].is_fluid();
+                if fluid.element[y][x] == Ele::Fluid {
+                    sides[y * width + x] = Oo::construct(x, y, fluid).afflicted_area();
+                }
+            }
+        }
+
+        let threads: usize = self.thread_count().clamp(1, height);
+        let bounds: Vec<usize> = (0..=threads).map(|band| band * height / threads).collect();
+        let (overrelaxation, scale) = (fluid.overrelaxation, fluid.pressure_scale());
+        let (iters, tolerance, norm) = (fluid.iters, fluid.tolerance, fluid.residual);
+
+        let mut correction: Vec<f32> = vec![0.0; width * height];
+        let mut pressure = split_rows(fluid.pressure.as_mut_slice(), width, &bounds, 0).into_iter();
+        let shared = Shared {
+            width,
+            height,
+            sides,
+            open,
+            u: locked(split_rows(fluid.u.as_mut_slice(), width + 1, &bounds, 0)),
+            v: locked(split_rows(fluid.v.as_mut_slice(), width, &bounds, 1)),
+            correction: locked(split_rows(&mut correction, width, &bounds, 0)),
+            rows: Mutex::new(vec![(0.0, 0.0, 0); height]),
+            barrier: Barrier::new(threads),
+            bounds,
+            overrelaxation,
+            scale,
+            iters,
+            tolerance,
+            norm,
+        };
+
+        let first: &mut [f32] = pressure.next().unwrap();
+        let iterations: usize = thread::scope(|scope| {
+            let shared = &shared;
+            for (band, slice) in pressure.enumerate() {
+                scope.spawn(move || shared.work(band + 1, slice));
+            }
+            shared.work(0, first)
+        });
+        drop(shared);
+
+        SolveReport { iterations, residual: fluid.residual(fluid.residual) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::advected_fluid;
+    use super::*;
+
+    #[test]
+    fn thread_count_doesnt_change_the_result() {
+        // a fixed sweep count, and a tolerance so the shared residual check decides when to stop
+        for tolerance in [0.0, 5.0] {
+            let solved = |threads: usize| -> Fluid {
+                let mut fluid: Fluid = advected_fluid(40, 30);
+                (fluid.iters, fluid.tolerance) = (60, tolerance);
+                fluid.project_with(&mut RedBlack::construct(threads));
+                fluid
+            };
+
+            let single: Fluid = solved(1);
+            assert!(single.solve.iterations > 0);
+            for threads in [3, 7] {
+                let split: Fluid = solved(threads);
+                assert_eq!(split.u, single.u);
+                assert_eq!(split.v, single.v);
+                assert_eq!(split.pressure, single.pressure);
+                assert_eq!(split.solve, single.solve);
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! drawing of the fluid state, kept separate from the solver itself
+//!
+//! a <Renderer> only has to know how to draw a handful of primitives - the shared visuals
+//! (gradient fill, vector field, streamlines, pressure) are provided on top of those, so every backend
+//! produces the same picture from the same read-only <FieldView> of the fluid
+
+pub mod software;
+#[cfg(feature = "gui")]
+pub mod window;
+
+use crate::config;
+use crate::fluid;
+use crate::utils;
+
+use config::VisualMode;
+use fluid::{Ele, Fluid};
+use utils::{get_color_vec, Vector};
+
+pub use software::SoftwareRenderer;
+#[cfg(feature = "gui")]
+pub use window::WindowRenderer;
+
+/// backend independent 8-bit rgba color
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub fn construct(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+
+    /// builds an opaque color from a 0xRRGGBB literal
+    pub fn from_hex(hex: u32) -> Rgba {
+        Rgba { r: (hex >> 16) as u8, g: (hex >> 8) as u8, b: hex as u8, a: 255 }
+    }
+}
+
+pub const WHITE: Rgba = Rgba { r: 255, g: 255, b: 255, a: 255 };
+
+/// read-only view of the fluid handed to renderers. the cell size and colour range are carried here
+/// instead of being read off the fluid so the same state can be drawn at any resolution and scale
+pub struct FieldView<'a> {
+    pub fluid: &'a Fluid,
+    pub cell_size: f32,
+
+    /// speed drawn at the top of the colour map
+    pub colour_max: f32,
+}
+
+impl<'a> FieldView<'a> {
+    pub fn construct(fluid: &'a Fluid) -> FieldView<'a> {
+        FieldView::with_cell_size(fluid, fluid.cell_size)
+    }
+
+    pub fn with_cell_size(fluid: &'a Fluid, cell_size: f32) -> FieldView<'a> {
+        FieldView { fluid, cell_size, colour_max: fluid.source_velocity * fluid.visual_modifier }
+    }
+
+    /// pixel dimensions of the whole grid at the view's cell size
+    pub fn extent(&self) -> (usize, usize) {
+        (
+            (self.fluid.x as f32 * self.cell_size).ceil() as usize,
+            (self.fluid.y as f32 * self.cell_size).ceil() as usize,
+        )
+    }
+
+    /// sum of the face velocities surrounding a cell - matches what the window has always shown
+    pub fn cell_velocity(&self, x: usize, y: usize) -> Vector<f32> {
+        let (left, right) = self.fluid.u.cell_faces(x, y);
+        let (top, bottom) = self.fluid.v.cell_faces(x, y);
+        Vector::construct(right + left, bottom + top)
+    }
+}
+
+/// drawing backend. implementors supply the primitives and get the fluid visuals for free
+pub trait Renderer {
+    fn clear(&mut self, color: Rgba);
+
+    fn draw_rectangle(&mut self, x: f32, y: f32, w: f32, h: f32, color: Rgba);
+
+    fn draw_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, thickness: f32, color: Rgba);
+
+    fn draw_circle(&mut self, x: f32, y: f32, radius: f32, color: Rgba);
+
+    /// draws the element grid and then either a velocity gradient fill or a vector field
+    #[allow(clippy::too_many_arguments)]
+    fn display(
+        &mut self, view: &FieldView, head: bool, draw_bounds: bool, normalize: bool, thickness: f32,
+        head_size: f32, spacing: usize, vector: bool, fill: bool,
+    ) {
+        let fluid = view.fluid;
+        let cell_size = view.cell_size;
+
+        for y in 0..fluid.y {
+            for x in 0..fluid.x {
+                let color: Rgba = match fluid.element[y][x] {
+                    Ele::Fluid => continue,
+                    Ele::Static => Rgba::from_hex(0x000000),
+                    Ele::Source(_) => Rgba::from_hex(0x1b85b8),
+                    Ele::Clone(_) => Rgba::from_hex(0x559e83),
+                };
+                self.draw_rectangle(x as f32 * cell_size, y as f32 * cell_size, cell_size, cell_size, color);
+            }
+        }
+        for y in 0..fluid.y {
+            for x in 0..fluid.x {
+                if !draw_bounds && fluid.element[y][x] != Ele::Fluid {
+                    continue;
+                }
+                if x % spacing != 0 || y % spacing != 0 {
+                    continue;
+                }
+
+                let mut velocity = view.cell_velocity(x, y);
+                let color: Rgba = get_color_vec(&velocity, view.colour_max, 1.0);
+
+                if vector {
+                    let nsize = cell_size * 2.5;
+                    if normalize {
+                        velocity.normalize();
+                        velocity.x *= nsize;
+                        velocity.y *= nsize;
+                    }
+
+                    let start: Vector<f32> = Vector {
+                        x: x as f32 * cell_size + cell_size / 2.0,
+                        y: y as f32 * cell_size + cell_size / 2.0,
+                    };
+                    self.draw_line(start.x, start.y, start.x + velocity.x, start.y + velocity.y, thickness, color);
+
+                    if head {
+                        self.draw_circle(start.x + velocity.x, start.y + velocity.y, head_size, color);
+                    }
+                }
+                else if fill {
+                    self.draw_rectangle(x as f32 * cell_size, y as f32 * cell_size, cell_size, cell_size, color);
+                }
+            }
+        }
+    }
+
+    /// traces streamlines seeded on a regular lattice and draws them as white polylines
+    fn streamline(
+        &mut self, view: &FieldView, spacing_x: usize, spacing_y: usize, max_steps: usize, step_size: f32,
+        thickness: f32,
+    ) {
+        let fluid = view.fluid;
+        let cell_size = view.cell_size;
+
+        for y in (0..fluid.y).step_by(spacing_y) {
+            for x in (0..fluid.x).step_by(spacing_x) {
+                let seed = Vector::construct(x as f32, y as f32);
+                let streamline = fluid.compute_streamline(seed, max_steps, step_size);
+
+                for pair in streamline.windows(2) {
+                    self.draw_line(
+                        pair[0].x * cell_size,
+                        pair[0].y * cell_size,
+                        pair[1].x * cell_size,
+                        pair[1].y * cell_size,
+                        thickness,
+                        WHITE,
+                    );
+                }
+            }
+        }
+    }
+
+    /// diverging fill of the recovered pressure - red above zero, blue below, white at zero. the colour
+    /// range is the largest magnitude on the grid, so the picture shows shape rather than absolute values
+    fn pressure(&mut self, view: &FieldView) {
+        let fluid = view.fluid;
+        let cell_size = view.cell_size;
+
+        let peak: f32 = fluid.pressure.as_slice().iter().fold(0.0, |max: f32, p| max.max(p.abs()));
+        for y in 0..fluid.y {
+            for x in 0..fluid.x {
+                let color: Rgba = match fluid.element[y][x] {
+                    Ele::Fluid => {
+                        let t: f32 = if peak > 0.0 { (fluid.pressure[y][x] / peak).clamp(-1.0, 1.0) } else { 0.0 };
+                        let fade = (255.0 * (1.0 - t.abs())) as u8;
+                        if t >= 0.0 {
+                            Rgba::construct(255, fade, fade, 255)
+                        }
+                        else {
+                            Rgba::construct(fade, fade, 255, 255)
+                        }
+                    }
+                    Ele::Static => Rgba::from_hex(0x000000),
+                    Ele::Source(_) => Rgba::from_hex(0x1b85b8),
+                    Ele::Clone(_) => Rgba::from_hex(0x559e83),
+                };
+                self.draw_rectangle(x as f32 * cell_size, y as f32 * cell_size, cell_size, cell_size, color);
+            }
+        }
+    }
+
+    /// draws one of the standard visual modes. blank mode draws nothing so long running sims
+    /// skip the draw overhead entirely
+    fn visual(&mut self, view: &FieldView, mode: &VisualMode) {
+        match mode {
+            VisualMode::Gradient => {
+                self.display(view, true, false, false, 0.4, 0.7, 1, false, true);
+            }
+            VisualMode::Vector => {
+                self.display(view, true, true, false, 0.4, 0.7, 1, true, false);
+            }
+            VisualMode::Other => {
+                self.display(view, true, false, false, 0.4, 0.7, 1, false, true);
+                self.streamline(view, 30, 10, 100, 0.07, 0.2);
+            }
+            VisualMode::Streamline => {
+                self.display(view, false, false, false, 1.0, 10.0, 1000, false, true);
+                self.streamline(view, 30, 8, 155, 0.05, 0.2);
+            }
+            VisualMode::Pressure => {
+                self.pressure(view);
+            }
+            VisualMode::Blank => {}
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::error;
+
+use std::{fs, io::BufWriter};
+
+use super::{FieldView, Renderer, Rgba};
+use error::FluidError;
+
+/// cpu rasteriser writing straight into an rgba8 buffer. used on machines with no window or gpu -
+/// the buffer is row-major, top-left origin, 4 bytes per pixel
+pub struct SoftwareRenderer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl SoftwareRenderer {
+    pub fn construct(width: usize, height: usize) -> SoftwareRenderer {
+        SoftwareRenderer { width, height, pixels: vec![0; width * height * 4] }
+    }
+
+    /// allocates a buffer exactly large enough to hold the view at its cell size
+    pub fn for_view(view: &FieldView) -> SoftwareRenderer {
+        let (width, height) = view.extent();
+        SoftwareRenderer::construct(width, height)
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgba {
+        let idx = (y * self.width + x) * 4;
+        Rgba::construct(self.pixels[idx], self.pixels[idx + 1], self.pixels[idx + 2], self.pixels[idx + 3])
+    }
+
+    /// writes the buffer out as an 8-bit rgba png
+    pub fn write_png(&self, path: &str) -> Result<(), FluidError> {
+        let io_error = |reason: String| FluidError::Io { path: path.to_string(), reason };
+
+        let file = fs::File::create(path).map_err(|err| io_error(err.to_string()))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(|err| io_error(err.to_string()))?;
+        writer.write_image_data(&self.pixels).map_err(|err| io_error(err.to_string()))
+    }
+
+    /// source-over blend of a color into a single pixel, with coverage scaling the color's alpha.
+    /// anything off the buffer is silently dropped
+    fn blend(&mut self, x: i64, y: i64, color: Rgba, coverage: f32) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+
+        let idx = (y as usize * self.width + x as usize) * 4;
+        let alpha = color.a as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+        let src = [color.r, color.g, color.b];
+        for (channel, value) in src.iter().enumerate() {
+            let dst = self.pixels[idx + channel] as f32;
+            self.pixels[idx + channel] = (*value as f32 * alpha + dst * (1.0 - alpha)).round() as u8;
+        }
+        let dst_a = self.pixels[idx + 3] as f32 / 255.0;
+        self.pixels[idx + 3] = ((alpha + dst_a * (1.0 - alpha)) * 255.0).round() as u8;
+    }
+
+    /// clamps a float pixel span to the integer range of pixels whose centres it covers
+    fn span(lo: f32, hi: f32, limit: usize) -> (i64, i64) {
+        let lo = (lo - 0.5).ceil().max(0.0) as i64;
+        let hi = ((hi - 0.5).ceil() as i64).min(limit as i64);
+        (lo, hi)
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn clear(&mut self, color: Rgba) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+    }
+
+    fn draw_rectangle(&mut self, x: f32, y: f32, w: f32, h: f32, color: Rgba) {
+        let (x0, x1) = Self::span(x, x + w, self.width);
+        let (y0, y1) = Self::span(y, y + h, self.height);
+        for py in y0..y1 {
+            for px in x0..x1 {
+                self.blend(px, py, color, 1.0);
+            }
+        }
+    }
+
+    /// lines thinner than a pixel are drawn one pixel wide with their alpha scaled down, which keeps
+    /// dense streamline plots looking the same as the antialiased window output
+    fn draw_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, thickness: f32, color: Rgba) {
+        let half = thickness.max(1.0) * 0.5;
+        let coverage = thickness.min(1.0);
+
+        let (dx, dy) = (x1 - x0, y1 - y0);
+        let length_sq = dx * dx + dy * dy;
+
+        let (px0, px1) = Self::span(x0.min(x1) - half, x0.max(x1) + half, self.width);
+        let (py0, py1) = Self::span(y0.min(y1) - half, y0.max(y1) + half, self.height);
+        for py in py0..py1 {
+            for px in px0..px1 {
+                let (cx, cy) = (px as f32 + 0.5, py as f32 + 0.5);
+                let t = if length_sq > 0.0 {
+                    (((cx - x0) * dx + (cy - y0) * dy) / length_sq).clamp(0.0, 1.0)
+                }
+                else {
+                    0.0
+                };
+                let (nx, ny) = (x0 + t * dx - cx, y0 + t * dy - cy);
+                if nx * nx + ny * ny <= half * half {
+                    self.blend(px, py, color, coverage);
+                }
+            }
+        }
+    }
+
+    fn draw_circle(&mut self, x: f32, y: f32, radius: f32, color: Rgba) {
+        let (px0, px1) = Self::span(x - radius, x + radius, self.width);
+        let (py0, py1) = Self::span(y - radius, y + radius, self.height);
+        for py in py0..py1 {
+            for px in px0..px1 {
+                let (nx, ny) = (px as f32 + 0.5 - x, py as f32 + 0.5 - y);
+                if nx * nx + ny * ny <= radius * radius {
+                    self.blend(px, py, color, 1.0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use macroquad::prelude::*;
+
+use super::{Renderer, Rgba};
+
+/// draws straight into the macroquad window
+pub struct WindowRenderer;
+
+impl From<Rgba> for Color {
+    fn from(color: Rgba) -> Color {
+        Color::from_rgba(color.r, color.g, color.b, color.a)
+    }
+}
+
+impl Renderer for WindowRenderer {
+    fn clear(&mut self, color: Rgba) {
+        clear_background(color.into());
+    }
+
+    fn draw_rectangle(&mut self, x: f32, y: f32, w: f32, h: f32, color: Rgba) {
+        draw_rectangle(x, y, w, h, color.into());
+    }
+
+    fn draw_line(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, thickness: f32, color: Rgba) {
+        draw_line(x0, y0, x1, y1, thickness, color.into());
+    }
+
+    fn draw_circle(&mut self, x: f32, y: f32, radius: f32, color: Rgba) {
+        draw_circle(x, y, radius, color.into());
+    }
+}
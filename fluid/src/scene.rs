@@ -0,0 +1,490 @@
+//! declarative scene files
+//!
+//! a scene lists what goes on each edge of the grid and which obstacles sit inside it, one directive
+//! per line, applied top to bottom so later lines paint over earlier ones:
+//!
+//! ```text
+//! # inflow on the left, outflow copies its neighbour on the right
+//! edge left source 145 0
+//! edge right clone -1 0
+//! edge top static
+//! edge bottom static
+//!
+//! circle 20% 50% 9%
+//! rectangle 60 10 70 40
+//! polygon 100 50  140 60  100 70
+//! ```
+//!
+//! <naca DIGITS X Y CHORD [aoa DEG]> generates a 4- or 5-digit naca section with its leading edge at
+//! (X, Y), pitched nose up by DEG about the quarter chord:
+//!
+//! ```text
+//! naca 2412 25% 50% 40% aoa 5
+//! ```
+//!
+//! <airfoil PATH X Y CHORD [aoa DEG]> does the same with a selig or lednicer coordinate file, its path
+//! relative to the scene file
+//!
+//! a <mask PATH> line paints cells from a bitmap instead, see <mask>. it takes <threshold N> to set
+//! how dark a cell has to be to become Static, and any number of <colour RRGGBB KIND> mappings:
+//!
+//! ```text
+//! mask wing.png threshold 100 colour ff0000 source 145 0 colour 00ff00 clone -1 0
+//! ```
+//!
+//! lengths are in cells, or a percentage of the grid when suffixed with <%> - x values scale with the
+//! width, y values and radii with the height. edges take a cell kind (<fluid>, <static>,
+//! <source [vx vy]>, <clone [dx dy]>) and optionally <span FROM TO> and <stride N> to cover only part
+//! of the edge. a source without a velocity uses the configured source velocity, and a clone without
+//! an offset copies the cell one step into the grid
+
+use crate::airfoil;
+use crate::error;
+use crate::mask;
+use crate::utils;
+
+use std::{fmt, fs, path::Path};
+
+use airfoil::{Airfoil, Naca, NACA_SAMPLES};
+use error::FluidError;
+use mask::{Mask, MaskRules};
+use utils::Vector;
+
+/// one side of the grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+    fn parse(token: &str) -> Result<Edge, String> {
+        match token {
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "top" => Ok(Self::Top),
+            "bottom" => Ok(Self::Bottom),
+            _ => Err(format!("unknown edge '{}', expected left, right, top or bottom", token)),
+        }
+    }
+
+    /// offset pointing from the edge into the grid
+    pub fn inward(&self) -> Vector<isize> {
+        match self {
+            Self::Left => Vector::construct(1, 0),
+            Self::Right => Vector::construct(-1, 0),
+            Self::Top => Vector::construct(0, 1),
+            Self::Bottom => Vector::construct(0, -1),
+        }
+    }
+
+    /// cell coordinate of position <along> on this edge of a width x height grid
+    pub fn cell(&self, along: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Self::Left => (0, along),
+            Self::Right => (width - 1, along),
+            Self::Top => (along, 0),
+            Self::Bottom => (along, height - 1),
+        }
+    }
+
+    /// number of cells along this edge
+    pub fn len(&self, width: usize, height: usize) -> usize {
+        match self {
+            Self::Left | Self::Right => height,
+            Self::Top | Self::Bottom => width,
+        }
+    }
+}
+
+/// a distance in cells, or a fraction of some grid dimension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Cells(f32),
+    Percent(f32),
+}
+
+impl Length {
+    fn parse(token: &str) -> Result<Length, String> {
+        let (number, percent) = match token.strip_suffix('%') {
+            Some(number) => (number, true),
+            None => (token, false),
+        };
+        let value: f32 = number.parse().map_err(|_| format!("expected a number, got '{}'", token))?;
+        if !value.is_finite() {
+            return Err(format!("expected a finite number, got '{}'", token));
+        }
+
+        Ok(if percent { Self::Percent(value) } else { Self::Cells(value) })
+    }
+
+    /// length in cells against an axis of <extent> cells
+    pub fn resolve(&self, extent: usize) -> f32 {
+        match self {
+            Self::Cells(cells) => *cells,
+            Self::Percent(percent) => percent / 100.0 * extent as f32,
+        }
+    }
+}
+
+/// what an edge directive paints its cells with. velocities and offsets are optional so a scene can
+/// defer to the config and the edge's geometry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellKind {
+    Fluid,
+    Static,
+    Source(Option<Vector<f32>>),
+    Clone(Option<Vector<isize>>),
+}
+
+impl CellKind {
+    /// parses a kind from the front of <tokens>, returning how many tokens it used
+    fn parse(tokens: &[&str]) -> Result<(CellKind, usize), String> {
+        match tokens.first().copied() {
+            Some("fluid") => Ok((Self::Fluid, 1)),
+            Some("static") => Ok((Self::Static, 1)),
+            Some("source") => match value_pair(&tokens[1..]) {
+                Some((vx, vy)) => {
+                    let vx: f32 = vx.parse().map_err(|_| format!("expected a source x velocity, got '{}'", vx))?;
+                    let vy: f32 = vy.parse().map_err(|_| format!("expected a source y velocity, got '{}'", vy))?;
+                    Ok((Self::Source(Some(Vector::construct(vx, vy))), 3))
+                }
+                None => Ok((Self::Source(None), 1)),
+            },
+            Some("clone") => match value_pair(&tokens[1..]) {
+                Some((dx, dy)) => {
+                    let dx: isize = dx.parse().map_err(|_| format!("expected a whole clone x offset, got '{}'", dx))?;
+                    let dy: isize = dy.parse().map_err(|_| format!("expected a whole clone y offset, got '{}'", dy))?;
+                    Ok((Self::Clone(Some(Vector::construct(dx, dy))), 3))
+                }
+                None => Ok((Self::Clone(None), 1)),
+            },
+            Some(other) => Err(format!("unknown cell kind '{}', expected fluid, static, source or clone", other)),
+            None => Err(String::from("missing cell kind")),
+        }
+    }
+}
+
+/// writes the kind back out the way <parse> reads it
+impl fmt::Display for CellKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fluid => write!(f, "fluid"),
+            Self::Static => write!(f, "static"),
+            Self::Source(Some(velocity)) => write!(f, "source {} {}", velocity.x, velocity.y),
+            Self::Source(None) => write!(f, "source"),
+            Self::Clone(Some(offset)) => write!(f, "clone {} {}", offset.x, offset.y),
+            Self::Clone(None) => write!(f, "clone"),
+        }
+    }
+}
+
+/// the two values following a cell kind, if the next token isn't already an edge option
+fn value_pair<'a>(tokens: &[&'a str]) -> Option<(&'a str, &'a str)> {
+    match tokens {
+        [a, b, ..] if !matches!(*a, "span" | "stride" | "threshold" | "colour") => Some((a, b)),
+        _ => None,
+    }
+}
+
+/// boundary condition painted along (part of) one edge
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeRule {
+    pub edge: Edge,
+    pub kind: CellKind,
+    /// start (inclusive) and end (exclusive) along the edge. the whole edge when missing
+    pub span: Option<(Length, Length)>,
+    /// only every n-th cell of the span is painted
+    pub stride: usize,
+}
+
+impl EdgeRule {
+    /// cells along the edge this rule paints, clipped to the grid
+    pub fn positions(&self, width: usize, height: usize) -> impl Iterator<Item = usize> {
+        let len = self.edge.len(width, height);
+        let (from, to) = match self.span {
+            Some((from, to)) => (
+                from.resolve(len).round().clamp(0.0, len as f32) as usize,
+                to.resolve(len).round().clamp(0.0, len as f32) as usize,
+            ),
+            None => (0, len),
+        };
+        (from..to.max(from)).step_by(self.stride)
+    }
+}
+
+/// solid obstacle primitives
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Circle { x: Length, y: Length, radius: Length },
+    Rectangle { x0: Length, y0: Length, x1: Length, y1: Length },
+    /// closed polygon, the last vertex joins back up with the first
+    Polygon { points: Vec<(Length, Length)> },
+    /// unit-chord section placed with its leading edge at (x, y). the chord scales with the width
+    Airfoil { airfoil: Airfoil, x: Length, y: Length, chord: Length, aoa: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    Edge(EdgeRule),
+    Obstacle(Shape),
+    /// the image is decoded when the scene is parsed, so a reset doesn't go back to disk
+    Mask { mask: Mask, rules: MaskRules },
+}
+
+/// parsed scene file. kept on the fluid so a reset rebuilds the same scene
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Scene {
+    pub directives: Vec<Directive>,
+}
+
+impl Scene {
+    pub fn from_file(path: &str) -> Result<Scene, FluidError> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|err| FluidError::Io { path: path.to_string(), reason: err.to_string() })?;
+        Scene::parse(path, &text)
+    }
+
+    /// parses scene text. <path> is only used to label errors
+    pub fn parse(path: &str, text: &str) -> Result<Scene, FluidError> {
+        let mut scene = Scene::default();
+
+        for (idx, raw) in text.lines().enumerate() {
+            let line = raw.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let directive = parse_directive(path, &tokens).map_err(|reason| FluidError::Parse {
+                path: path.to_string(),
+                line: idx + 1,
+                reason,
+            })?;
+            scene.directives.push(directive);
+        }
+
+        Ok(scene)
+    }
+
+    /// checks the scene makes sense on a width x height grid - currently that every clone cell, from an
+    /// edge or a mask, has an offset and that its master lands inside the grid
+    pub fn validate(&self, width: usize, height: usize) -> Result<(), FluidError> {
+        for directive in &self.directives {
+            match directive {
+                Directive::Edge(rule) => {
+                    let CellKind::Clone(offset) = rule.kind else {
+                        continue;
+                    };
+
+                    let offset = offset.unwrap_or(rule.edge.inward());
+                    for along in rule.positions(width, height) {
+                        let (x, y) = rule.edge.cell(along, width, height);
+                        check_master(x, y, offset, width, height)?;
+                    }
+                }
+                Directive::Mask { mask, rules } => {
+                    if !rules.colours.iter().any(|(_, kind)| matches!(kind, CellKind::Clone(_))) {
+                        continue;
+                    }
+
+                    for y in 0..height {
+                        for x in 0..width {
+                            match mask.classify(rules, x, y, width, height) {
+                                Some(CellKind::Clone(Some(offset))) => check_master(x, y, offset, width, height)?,
+                                // there's no edge for a mask cell to point inward from
+                                Some(CellKind::Clone(None)) => {
+                                    return Err(scene_error(format!("mask clone cell ({}, {}) has no offset", x, y)))
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn scene_error(reason: String) -> FluidError {
+    FluidError::InvalidConfig { key: String::from("scene"), reason }
+}
+
+/// a clone cell at (x, y) has to copy from some other cell inside the grid
+fn check_master(x: usize, y: usize, offset: Vector<isize>, width: usize, height: usize) -> Result<(), FluidError> {
+    let (mx, my) = (x as isize + offset.x, y as isize + offset.y);
+    if offset.x == 0 && offset.y == 0 {
+        return Err(scene_error(format!("clone cell ({}, {}) copies from itself", x, y)));
+    }
+    if mx < 0 || my < 0 || mx >= width as isize || my >= height as isize {
+        return Err(scene_error(format!(
+            "clone cell ({}, {}) copies from ({}, {}), which is outside the {}x{} grid",
+            x, y, mx, my, width, height
+        )));
+    }
+    Ok(())
+}
+
+fn parse_directive(path: &str, tokens: &[&str]) -> Result<Directive, String> {
+    let lengths = |tokens: &[&str]| -> Result<Vec<Length>, String> {
+        tokens.iter().map(|token| Length::parse(token)).collect()
+    };
+
+    match tokens[0] {
+        "edge" => {
+            let edge = Edge::parse(tokens.get(1).copied().unwrap_or(""))?;
+            let (kind, used) = CellKind::parse(&tokens[2.min(tokens.len())..])?;
+            let mut rule = EdgeRule { edge, kind, span: None, stride: 1 };
+
+            let mut rest = &tokens[(2 + used).min(tokens.len())..];
+            while !rest.is_empty() {
+                match rest {
+                    ["span", from, to, tail @ ..] => {
+                        rule.span = Some((Length::parse(from)?, Length::parse(to)?));
+                        rest = tail;
+                    }
+                    ["stride", stride, tail @ ..] => {
+                        rule.stride = stride
+                            .parse()
+                            .ok()
+                            .filter(|stride: &usize| *stride > 0)
+                            .ok_or(format!("stride must be a whole number above 0, got '{}'", stride))?;
+                        rest = tail;
+                    }
+                    _ => return Err(format!("unexpected '{}' in edge directive", rest.join(" "))),
+                }
+            }
+
+            Ok(Directive::Edge(rule))
+        }
+        "circle" => match lengths(&tokens[1..])?[..] {
+            [x, y, radius] => Ok(Directive::Obstacle(Shape::Circle { x, y, radius })),
+            _ => Err(String::from("circle takes x, y and radius")),
+        },
+        "rectangle" => match lengths(&tokens[1..])?[..] {
+            [x0, y0, x1, y1] => Ok(Directive::Obstacle(Shape::Rectangle { x0, y0, x1, y1 })),
+            _ => Err(String::from("rectangle takes two opposite corners, x0 y0 x1 y1")),
+        },
+        "polygon" => {
+            let values = lengths(&tokens[1..])?;
+            if values.len() < 6 || values.len() % 2 != 0 {
+                return Err(String::from("polygon takes at least 3 vertices as x y pairs"));
+            }
+            let points = values.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect();
+            Ok(Directive::Obstacle(Shape::Polygon { points }))
+        }
+        "naca" => {
+            let airfoil = Naca::parse(tokens.get(1).copied().unwrap_or(""))
+                .map_err(|err| err.to_string())?
+                .airfoil(NACA_SAMPLES);
+            placed_airfoil(airfoil, &tokens[2.min(tokens.len())..])
+        }
+        "airfoil" => {
+            let file = tokens.get(1).ok_or("airfoil takes a coordinate file path")?;
+            let base = Path::new(path).parent().unwrap_or(Path::new(""));
+            let airfoil =
+                Airfoil::from_dat(&base.join(file).to_string_lossy()).map_err(|err| err.to_string())?;
+            placed_airfoil(airfoil, &tokens[2..])
+        }
+        "mask" => {
+            let file = tokens.get(1).ok_or("mask takes an image path")?;
+            let mut rules = MaskRules::default();
+
+            let mut rest = &tokens[2..];
+            while !rest.is_empty() {
+                match rest {
+                    ["threshold", threshold, tail @ ..] => {
+                        rules.threshold =
+                            threshold.parse().map_err(|_| format!("threshold must be 0-255, got '{}'", threshold))?;
+                        rest = tail;
+                    }
+                    ["colour", colour, tail @ ..] => {
+                        let rgb = u32::from_str_radix(colour.trim_start_matches('#'), 16)
+                            .ok()
+                            .filter(|rgb| *rgb <= 0xffffff)
+                            .ok_or(format!("expected an RRGGBB colour, got '{}'", colour))?;
+                        let (kind, used) = CellKind::parse(tail)?;
+                        if kind == CellKind::Clone(None) {
+                            return Err(String::from("clone cells in a mask need an explicit dx dy offset"));
+                        }
+                        rules.colours.push((rgb, kind));
+                        rest = &tail[used..];
+                    }
+                    _ => return Err(format!("unexpected '{}' in mask directive", rest.join(" "))),
+                }
+            }
+
+            let mask = Mask::from_scene_path(path, file).map_err(|err| err.to_string())?;
+            Ok(Directive::Mask { mask, rules })
+        }
+        other => Err(format!(
+            "unknown directive '{}', expected edge, circle, rectangle, polygon, naca, airfoil or mask",
+            other
+        )),
+    }
+}
+
+/// reads the <X Y CHORD [aoa DEG]> placement shared by the airfoil directives
+fn placed_airfoil(airfoil: Airfoil, tokens: &[&str]) -> Result<Directive, String> {
+    let (x, y, chord, aoa) = match tokens {
+        [x, y, chord] => (x, y, chord, 0.0),
+        [x, y, chord, "aoa", aoa] => {
+            (x, y, chord, aoa.parse().map_err(|_| format!("angle of attack must be in degrees, got '{}'", aoa))?)
+        }
+        _ => return Err(String::from("airfoils take x, y, chord and optionally aoa DEG")),
+    };
+
+    let (x, y, chord) = (Length::parse(x)?, Length::parse(y)?, Length::parse(chord)?);
+    Ok(Directive::Obstacle(Shape::Airfoil { airfoil, x, y, chord, aoa }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::fluid::Fluid;
+
+    #[test]
+    fn unknown_directives_list_every_known_one() {
+        let err: String = Scene::parse("test.scene", "wing 0 0 10").unwrap_err().to_string();
+        for directive in ["edge", "circle", "rectangle", "polygon", "naca", "airfoil", "mask"] {
+            assert!(err.contains(directive), "'{}' missing from: {}", directive, err);
+        }
+    }
+
+    #[test]
+    fn mask_clones_are_validated_like_edge_clones() {
+        let masked = |offset: Option<Vector<isize>>| -> Scene {
+            // a red column down the left edge, mapped to clones
+            let mut mask = Mask::construct(10, 10, [255; 4]);
+            for y in 0..10 {
+                mask.pixels[y * 10] = [255, 0, 0, 255];
+            }
+            let mut rules = MaskRules::default();
+            rules.colours.push((0xff0000, CellKind::Clone(offset)));
+            Scene { directives: vec![Directive::Mask { mask, rules }] }
+        };
+
+        assert!(masked(Some(Vector::construct(1, 0))).validate(10, 10).is_ok());
+        for offset in [Some(Vector::construct(-1, 0)), Some(Vector::construct(0, 0)), None] {
+            assert!(masked(offset).validate(10, 10).is_err(), "{:?} was accepted", offset);
+        }
+    }
+    #[test]
+    fn channel_scene_is_the_built_in_layout() {
+        let config: Config = Config::new();
+        assert_eq!((config.x, config.y), (500, 500));
+        let built_in: Fluid = Fluid::from_config(&config).unwrap();
+
+        let mut from_scene: Fluid = Fluid::construct(&config);
+        from_scene.set_scene(Scene::parse("channel.scene", include_str!("../scenes/channel.scene")).unwrap()).unwrap();
+        from_scene.assert_boundary_conditions();
+
+        assert!(built_in.element == from_scene.element, "channel.scene differs from the built-in layout");
+    }
+}
@@ -0,0 +1,34 @@
+//! fixtures shared by the unit tests, so each module's tests build their fluids the same way
+
+use crate::config;
+use crate::fluid;
+
+use std::{fs, path::PathBuf};
+
+use config::Config;
+use fluid::Fluid;
+
+/// default config on a <width> x <height> grid
+pub(crate) fn sized(width: usize, height: usize) -> Config {
+    let mut config: Config = Config::new();
+    config.x = width;
+    config.y = height;
+    config
+}
+
+/// fluid built from <config> and stepped <steps> times with every stage on, as a run steps it
+pub(crate) fn stepped_fluid(config: &Config, steps: usize) -> Fluid {
+    let mut fluid: Fluid = Fluid::from_config(config).unwrap();
+    for _ in 0..steps {
+        fluid.update_fluid(true, true, true, true);
+    }
+    fluid
+}
+
+/// empty directory under the system temp dir, unique to this test process
+pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fluid-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -1,8 +1,13 @@
+#[cfg(feature = "gui")]
 use crate::fluid;
+use crate::render;
 
+#[cfg(feature = "gui")]
 use macroquad::prelude::*;
 
+#[cfg(feature = "gui")]
 use fluid::Fluid;
+use render::Rgba;
 
 /// 4 directions adjacent to a cell on a cartesian grid
 pub fn get_directions() -> [(isize, isize); 4] {
@@ -51,6 +56,7 @@ pub fn get_directions_26() -> [(isize, isize, isize); 26] {
     ]
 }
 
+#[cfg(feature = "gui")]
 fn iter_grid(x: usize, y: usize, bound: isize) -> impl Iterator<Item = (usize, usize)> {
     (-bound..=bound).flat_map(move |dx| {
         (-bound..=bound).map(move |dy| ((x as isize + dx) as usize, (y as isize + dy) as usize))
@@ -106,7 +112,7 @@ impl Clamp for f32 {
     }
 }
 
-#[derive(PartialEq, Clone, Copy, Debug, Eq, Hash)]
+#[derive(PartialEq, Clone, Copy, Debug, Eq, Hash, Default)]
 pub struct Vector<T> {
     pub x: T,
     pub y: T,
@@ -159,7 +165,7 @@ impl Vector<f32> {
     }
 }
 
-pub fn get_color_vec(vec: &Vector<f32>, max: f32, buffer_mult: f32) -> Color {
+pub fn get_color_vec(vec: &Vector<f32>, max: f32, buffer_mult: f32) -> Rgba {
     let max = max * buffer_mult;
 
     let mag = vec.magnitude();
@@ -177,7 +183,7 @@ pub fn get_color_vec(vec: &Vector<f32>, max: f32, buffer_mult: f32) -> Color {
     let value = 1.0;
     let (r, g, b) = hsv_to_rgb(hue, saturation, value);
 
-    Color::from_rgba(r, g, b, 200)
+    Rgba::construct(r, g, b, 200)
 }
 
 fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (u8, u8, u8) {
@@ -218,6 +224,29 @@ pub fn interpolate_f32(curr: Vector<f32>, prev: Vector<f32>) -> Vec<Vector<f32>>
     points
 }
 
+/// crc32 (ieee) lookup table, built at compile time
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[idx] = crc;
+        idx += 1;
+    }
+    table
+};
+
+/// crc32 (ieee), as used by checkpoints and zip archives
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+#[cfg(feature = "gui")]
 pub fn place_tool(prev: &mut Option<Vector<f32>>, fluid: &mut Fluid, mode: &str, size: usize) {
     match mode {
         "place" => {